@@ -0,0 +1,98 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+/// How many rows of the actual file are considered as a partner for each changed row of the expected file.
+const MAX_CANDIDATES: usize = 1000;
+
+/// How a row of one file lines up with the other, given by the index of the row in each file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Paired(usize, usize),
+    Missing(usize),
+    Extra(usize),
+    Moved(usize, usize),
+}
+
+/// Lines up the rows of the two files by their contents, the way a text diff lines up lines, given a hash of each
+/// row.  Where rows were changed, each row is paired with the most similar row that was changed in its place, as long
+/// as they are at least `threshold` similar.  A row that was deleted in one place and inserted unchanged in another is
+/// treated as moved.
+pub fn align(
+    expected: &[u64],
+    actual: &[u64],
+    similarity: impl Fn(usize, usize) -> f64,
+    threshold: f64,
+) -> Vec<Alignment> {
+    let mut alignments = vec![];
+    for op in capture_diff_slices(Algorithm::Myers, expected, actual) {
+        let (tag, expected_rows, actual_rows) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => alignments.extend(expected_rows.zip(actual_rows).map(
+                |(expected_row, actual_row)| Some(Alignment::Paired(expected_row, actual_row)),
+            )),
+            DiffTag::Replace => alignments.extend(
+                pair_similar(expected_rows, actual_rows, &similarity, threshold)
+                    .into_iter()
+                    .map(Some),
+            ),
+            DiffTag::Delete | DiffTag::Insert => {
+                alignments.extend(expected_rows.map(|row| Some(Alignment::Missing(row))));
+                alignments.extend(actual_rows.map(|row| Some(Alignment::Extra(row))));
+            }
+        }
+    }
+
+    // Each missing row is matched with the first unclaimed extra row with the same contents, wherever it is.
+    let mut extra_rows: HashMap<u64, VecDeque<usize>> = HashMap::new();
+    for (index, alignment) in alignments.iter().enumerate() {
+        if let Some(Alignment::Extra(row)) = alignment {
+            extra_rows.entry(actual[*row]).or_default().push_back(index);
+        }
+    }
+    for index in 0..alignments.len() {
+        if let Some(Alignment::Missing(expected_row)) = alignments[index] {
+            let extra_index = extra_rows
+                .get_mut(&expected[expected_row])
+                .and_then(VecDeque::pop_front);
+            if let Some(extra_index) = extra_index {
+                if let Some(Alignment::Extra(actual_row)) = alignments[extra_index].take() {
+                    alignments[index] = Some(Alignment::Moved(expected_row, actual_row));
+                }
+            }
+        }
+    }
+    alignments.into_iter().flatten().collect()
+}
+
+/// Pairs each changed row of the expected file with the most similar of the rows that replaced it, keeping the rows
+/// of both files in order.  Rows without a similar enough partner are missing or extra.
+fn pair_similar(
+    expected_rows: Range<usize>,
+    actual_rows: Range<usize>,
+    similarity: &impl Fn(usize, usize) -> f64,
+    threshold: f64,
+) -> Vec<Alignment> {
+    let mut alignments = vec![];
+    let mut next_actual_row = actual_rows.start;
+    for expected_row in expected_rows {
+        let mut best: Option<(usize, f64)> = None;
+        for actual_row in (next_actual_row..actual_rows.end).take(MAX_CANDIDATES) {
+            let score = similarity(expected_row, actual_row);
+            if score >= threshold && best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((actual_row, score));
+            }
+        }
+        match best {
+            Some((actual_row, _)) => {
+                alignments.extend((next_actual_row..actual_row).map(Alignment::Extra));
+                alignments.push(Alignment::Paired(expected_row, actual_row));
+                next_actual_row = actual_row + 1;
+            }
+            None => alignments.push(Alignment::Missing(expected_row)),
+        }
+    }
+    alignments.extend((next_actual_row..actual_rows.end).map(Alignment::Extra));
+    alignments
+}
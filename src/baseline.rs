@@ -0,0 +1,113 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// A problem as it is remembered in a baseline file of approved differences: what kind of problem it was, where it
+/// was and the values involved.  Cells are found by the key of their row when rows were paired up by key, and by
+/// record number otherwise.  Problems with whole rows are found by the row itself, since rows move around.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    pub problem: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub trailer: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl BaselineEntry {
+    pub fn new(problem: &str) -> Self {
+        BaselineEntry {
+            problem: problem.to_string(),
+            key: None,
+            line: None,
+            trailer: false,
+            column: None,
+            expected: None,
+            actual: None,
+            row: None,
+        }
+    }
+}
+
+impl Display for BaselineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problem)?;
+        if let Some(key) = &self.key {
+            write!(f, " at key {}", key)?;
+        }
+        if let Some(line) = self.line {
+            let record = if self.trailer {
+                "trailer record"
+            } else {
+                "record"
+            };
+            write!(f, " at {} {}", record, line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ", column {}", column)?;
+        }
+        if let Some(row) = &self.row {
+            write!(f, " {}", row)?;
+        }
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, " (expected {}, actual {})", expected, actual)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BaselineError {
+    IO(io::Error),
+    Json(serde_json::Error),
+}
+
+impl Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IO(error) => write!(f, "{}", error),
+            Self::Json(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for BaselineError {
+    fn from(error: io::Error) -> Self {
+        BaselineError::IO(error)
+    }
+}
+
+impl From<serde_json::Error> for BaselineError {
+    fn from(error: serde_json::Error) -> Self {
+        BaselineError::Json(error)
+    }
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<BaselineEntry>, BaselineError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn write<P: AsRef<Path>>(path: P, entries: &[BaselineEntry]) -> Result<(), BaselineError> {
+    let writer = BufWriter::new(File::create(path)?);
+    Ok(serde_json::to_writer_pretty(writer, entries)?)
+}
@@ -0,0 +1,222 @@
+use serde::Serialize;
+
+/// Cells whose lengths multiply to more than this are shown as one removal and one addition, rather than spending
+/// time and memory lining up their pieces.
+const MAX_DIFF_SIZE: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Same,
+    Removed,
+    Added,
+}
+
+/// A piece of a cell, with whether it is in both values or only one of them.
+#[derive(Debug, Clone, Serialize)]
+pub struct Segment {
+    pub text: String,
+    pub change: Change,
+}
+
+/// Splits a value into words, runs of whitespace and single other characters.  A value without whitespace is split
+/// into characters instead, so that a changed digit in a number or code doesn't mark the whole thing as changed.
+fn tokenize(value: &str) -> Vec<&str> {
+    if !value.contains(char::is_whitespace) {
+        return value
+            .char_indices()
+            .map(|(start, character)| &value[start..start + character.len_utf8()])
+            .collect();
+    }
+    let mut tokens = vec![];
+    let mut start = 0;
+    let mut previous_kind = None;
+    for (index, character) in value.char_indices() {
+        let kind = if character.is_whitespace() {
+            Some(0)
+        } else if character.is_alphanumeric() {
+            Some(1)
+        } else {
+            None
+        };
+        if index > start && (kind.is_none() || kind != previous_kind) {
+            tokens.push(&value[start..index]);
+            start = index;
+        }
+        previous_kind = kind;
+    }
+    if start < value.len() {
+        tokens.push(&value[start..]);
+    }
+    tokens
+}
+
+fn push_segment(segments: &mut Vec<Segment>, text: &str, change: Change) {
+    let text = match change {
+        Change::Same => make_visible(text, false),
+        _ => make_visible(text, true),
+    };
+    match segments.last_mut() {
+        Some(last) if last.change == change => last.text.push_str(&text),
+        _ => segments.push(Segment { text, change }),
+    }
+}
+
+/// Works out which pieces of the expected value were removed and which were added to make the actual value.
+pub fn diff(expected: &str, actual: &str) -> Vec<Segment> {
+    let expected_tokens = tokenize(expected);
+    let actual_tokens = tokenize(actual);
+    let mut segments = vec![];
+    if expected_tokens.len().saturating_mul(actual_tokens.len()) > MAX_DIFF_SIZE {
+        push_segment(&mut segments, expected, Change::Removed);
+        push_segment(&mut segments, actual, Change::Added);
+        return segments;
+    }
+
+    // The length of the longest common subsequence of the tokens from each position onwards.
+    let width = actual_tokens.len() + 1;
+    let mut common = vec![0usize; (expected_tokens.len() + 1) * width];
+    for expected_index in (0..expected_tokens.len()).rev() {
+        for actual_index in (0..actual_tokens.len()).rev() {
+            common[expected_index * width + actual_index] =
+                if expected_tokens[expected_index] == actual_tokens[actual_index] {
+                    common[(expected_index + 1) * width + actual_index + 1] + 1
+                } else {
+                    common[(expected_index + 1) * width + actual_index]
+                        .max(common[expected_index * width + actual_index + 1])
+                };
+        }
+    }
+
+    let (mut expected_index, mut actual_index) = (0, 0);
+    while expected_index < expected_tokens.len() || actual_index < actual_tokens.len() {
+        if expected_index < expected_tokens.len()
+            && actual_index < actual_tokens.len()
+            && expected_tokens[expected_index] == actual_tokens[actual_index]
+        {
+            push_segment(&mut segments, expected_tokens[expected_index], Change::Same);
+            expected_index += 1;
+            actual_index += 1;
+        } else if actual_index == actual_tokens.len()
+            || (expected_index < expected_tokens.len()
+                && common[(expected_index + 1) * width + actual_index]
+                    >= common[expected_index * width + actual_index + 1])
+        {
+            push_segment(
+                &mut segments,
+                expected_tokens[expected_index],
+                Change::Removed,
+            );
+            expected_index += 1;
+        } else {
+            push_segment(&mut segments, actual_tokens[actual_index], Change::Added);
+            actual_index += 1;
+        }
+    }
+    segments
+}
+
+/// Replaces characters that can't otherwise be seen with visible stand-ins.  Control characters are always replaced,
+/// and whitespace is too when `whitespace` is set, since that's when it matters.
+pub fn make_visible(text: &str, whitespace: bool) -> String {
+    let mut visible = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            ' ' if whitespace => visible.push('·'),
+            '\t' if whitespace => visible.push('→'),
+            '\n' if whitespace => visible.push('↵'),
+            '\r' if whitespace => visible.push('␍'),
+            '\u{a0}' if whitespace => visible.push('⍽'),
+            '\u{0}'..='\u{1f}' => {
+                visible.push(char::from_u32(0x2400 + character as u32).unwrap_or('?'))
+            }
+            '\u{7f}' => visible.push('␡'),
+            '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => {
+                visible.push_str(&format!("<U+{:04X}>", character as u32))
+            }
+            _ => visible.push(character),
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(expected: &str, actual: &str) -> Vec<(String, Change)> {
+        diff(expected, actual)
+            .into_iter()
+            .map(|segment| (segment.text, segment.change))
+            .collect()
+    }
+
+    fn segment(text: &str, change: Change) -> (String, Change) {
+        (text.to_string(), change)
+    }
+
+    #[test]
+    fn splits_values_without_whitespace_into_characters() {
+        assert_eq!(tokenize("1234"), vec!["1", "2", "3", "4"]);
+        assert_eq!(
+            tokenize("big  red, box"),
+            vec!["big", "  ", "red", ",", " ", "box"]
+        );
+        assert_eq!(
+            segments("1234", "1284"),
+            vec![
+                segment("12", Change::Same),
+                segment("3", Change::Removed),
+                segment("8", Change::Added),
+                segment("4", Change::Same),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_whitespace_only_where_it_changed() {
+        assert_eq!(
+            segments("apple pie", "apple  pie\t"),
+            vec![
+                segment("apple", Change::Same),
+                segment("·", Change::Removed),
+                segment("··", Change::Added),
+                segment("pie", Change::Same),
+                segment("→", Change::Added),
+            ]
+        );
+        assert_eq!(
+            segments("a b\r\n", "a b\n"),
+            vec![
+                segment("a b", Change::Same),
+                segment("␍↵", Change::Removed),
+                segment("↵", Change::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn shows_long_values_as_one_removal_and_one_addition() {
+        let expected = "1".repeat(1001);
+        let actual = format!("{}2", "1".repeat(1000));
+        assert!(expected.len() * actual.len() > MAX_DIFF_SIZE);
+        assert_eq!(
+            segments(&expected, &actual),
+            vec![
+                segment(&expected, Change::Removed),
+                segment(&actual, Change::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_characters_that_cannot_be_seen() {
+        assert_eq!(make_visible("a b\t\u{a0}", true), "a·b→⍽");
+        assert_eq!(make_visible("a b\t\u{a0}", false), "a b␉\u{a0}");
+        assert_eq!(make_visible("\u{1}\u{7f}", false), "␁␡");
+        assert_eq!(
+            make_visible("a\u{200b}b\u{feff}", false),
+            "a<U+200B>b<U+FEFF>"
+        );
+    }
+}
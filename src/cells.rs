@@ -0,0 +1,184 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::normalize::Normalization;
+use crate::rewrite::Rewrite;
+use crate::sources::ColumnType;
+
+/// The values that stand for a missing value in the files, like `NULL` or `\N`.  Cells holding any of them are equal
+/// to each other.
+#[derive(Debug, Clone, Default)]
+pub struct Nulls {
+    pub tokens: Vec<String>,
+    /// Whether a null against a value is reported as its own kind of problem, rather than as a mismatched cell.
+    pub report_separately: bool,
+}
+
+impl Nulls {
+    pub const DEFAULT_TOKENS: [&'static str; 5] = ["", "NULL", "\\N", "NA", "null"];
+
+    fn is_null(&self, value: &str) -> bool {
+        self.tokens.iter().any(|token| token == value)
+    }
+}
+
+/// What every null is written as when rows are matched by their values.
+const NULL_KEY: &str = "\u{0}null";
+
+/// How a pair of cells compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellComparison {
+    Same,
+    Different,
+    /// One cell was null and the other had a value.
+    NullAgainstValue,
+    /// The expected cell could be read as the column's type, but the actual cell couldn't.
+    WrongType,
+}
+
+/// How the cells of one column are compared.
+#[derive(Debug, Clone)]
+pub struct ColumnComparison {
+    pub column_type: ColumnType,
+    /// Whether an actual cell that can't be read as the column's type is its own kind of problem.
+    pub checks_type: bool,
+    pub rewrites: Vec<Rewrite>,
+    pub normalization: Normalization,
+    /// Whether the column is compared at all, since only some columns might have been picked.
+    pub compared: bool,
+}
+
+impl ColumnComparison {
+    fn rewrite<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        self.rewrites
+            .iter()
+            .fold(Cow::Borrowed(value), |value, rewrite| {
+                match rewrite.apply(&value) {
+                    Cow::Borrowed(_) => value,
+                    Cow::Owned(rewritten) => Cow::Owned(rewritten),
+                }
+            })
+    }
+
+    /// Describes the rewrite rules that changed either cell, if any did.
+    pub fn rewrites_applied(&self, expected: &str, actual: &str) -> Option<String> {
+        let mut expected = Cow::Borrowed(expected);
+        let mut actual = Cow::Borrowed(actual);
+        let mut applied = vec![];
+        for rewrite in &self.rewrites {
+            let mut changed = false;
+            if let Cow::Owned(rewritten) = rewrite.apply(&expected) {
+                expected = Cow::Owned(rewritten);
+                changed = true;
+            }
+            if let Cow::Owned(rewritten) = rewrite.apply(&actual) {
+                actual = Cow::Owned(rewritten);
+                changed = true;
+            }
+            if changed {
+                applied.push(rewrite.to_string());
+            }
+        }
+        if applied.is_empty() {
+            None
+        } else {
+            Some(applied.join(", then "))
+        }
+    }
+
+    /// The cell as it is compared, after being rewritten and normalized.
+    pub fn canonical<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        match self.rewrite(value) {
+            Cow::Borrowed(value) => self.normalization.apply(value),
+            Cow::Owned(value) => Cow::Owned(self.normalization.apply(&value).into_owned()),
+        }
+    }
+
+    pub fn compare(&self, expected: &str, actual: &str, nulls: &Nulls) -> CellComparison {
+        let expected = self.canonical(expected);
+        let actual = self.canonical(actual);
+        match (nulls.is_null(&expected), nulls.is_null(&actual)) {
+            (true, true) => CellComparison::Same,
+            (true, false) | (false, true) if nulls.report_separately => {
+                CellComparison::NullAgainstValue
+            }
+            _ if self.column_type.cells_match(&expected, &actual) => CellComparison::Same,
+            _ if self.checks_type
+                && self.column_type.parses(&expected)
+                && !self.column_type.parses(&actual) =>
+            {
+                CellComparison::WrongType
+            }
+            _ => CellComparison::Different,
+        }
+    }
+}
+
+/// A cell as rows are matched by their values, written the same way as every cell it compares the same as.  The cell
+/// is rewritten and normalized, every null is written the same way, and values of the column's type are written as
+/// their value, so `1.0` is written as `1`.
+pub fn cell_key<'a>(
+    columns: &[ColumnComparison],
+    column: usize,
+    cell: &'a str,
+    nulls: &Nulls,
+) -> Cow<'a, str> {
+    let (cell, column_type) = match columns.get(column) {
+        Some(comparison) => (comparison.canonical(cell), comparison.column_type),
+        None => (Cow::Borrowed(cell), ColumnType::Text),
+    };
+    if nulls.is_null(&cell) {
+        return Cow::Borrowed(NULL_KEY);
+    }
+    match column_type.canonical(&cell) {
+        Some(canonical) => Cow::Owned(canonical),
+        None => cell,
+    }
+}
+
+/// Hashes a row as it is compared, with each cell written as its key, so that rows that only differ in ways being
+/// ignored hash the same.
+pub fn row_hash(record: &csv::StringRecord, columns: &[ColumnComparison], nulls: &Nulls) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (column, cell) in record.iter().enumerate() {
+        match columns.get(column) {
+            Some(comparison) if !comparison.compared => continue,
+            _ => cell_key(columns, column, cell, nulls).hash(&mut hasher),
+        }
+    }
+    hasher.finish()
+}
+
+/// The fraction of compared cells that are the same in both rows, counting cells missing from the shorter row as
+/// different.
+pub fn row_similarity(
+    expected: &csv::StringRecord,
+    actual: &csv::StringRecord,
+    columns: &[ColumnComparison],
+    nulls: &Nulls,
+) -> f64 {
+    let num_cells = (0..expected.len().max(actual.len()))
+        .filter(|column| {
+            columns
+                .get(*column)
+                .is_none_or(|comparison| comparison.compared)
+        })
+        .count();
+    if num_cells == 0 {
+        return 1.0;
+    }
+    let num_same = expected
+        .iter()
+        .zip(actual.iter())
+        .enumerate()
+        .filter(|(column, (expected, actual))| match columns.get(*column) {
+            Some(comparison) => {
+                comparison.compared
+                    && comparison.compare(expected, actual, nulls) == CellComparison::Same
+            }
+            None => expected == actual,
+        })
+        .count();
+    num_same as f64 / num_cells as f64
+}
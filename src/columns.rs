@@ -0,0 +1,94 @@
+use std::fmt::{self, Display};
+
+/// A column picked out on the command line, either by its name or by its number starting from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnSelector {
+    Name(String),
+    Number(usize),
+}
+
+impl ColumnSelector {
+    pub fn parse(value: &str) -> Self {
+        match value.parse::<usize>() {
+            Ok(number) if number > 0 => ColumnSelector::Number(number),
+            _ => ColumnSelector::Name(value.to_string()),
+        }
+    }
+
+    /// Finds the index of the column among the given column names, or nothing if there's no such column.  A number is
+    /// also tried as a name first, in case a column is named after one.
+    pub fn resolve(&self, column_names: &[String]) -> Option<usize> {
+        match self {
+            ColumnSelector::Name(name) => column_names.iter().position(|column| column == name),
+            ColumnSelector::Number(number) => column_names
+                .iter()
+                .position(|column| *column == number.to_string())
+                .or_else(|| Some(number - 1).filter(|_| *number <= column_names.len())),
+        }
+    }
+}
+
+/// Columns picked out on the command line, either a single column or a range of column numbers like `3-7`, which can
+/// leave out its end to run to the last column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnRange {
+    Column(ColumnSelector),
+    Numbers(usize, Option<usize>),
+}
+
+impl ColumnRange {
+    /// Parses a list of columns and ranges separated by commas.  Anything that isn't a range of numbers is taken as a
+    /// single column, so names with dashes in them still work.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .map(|item| {
+                let range = item.split_once('-').and_then(|(start, end)| {
+                    let start = start.parse::<usize>().ok().filter(|start| *start > 0)?;
+                    match end {
+                        "" => Some((start, None)),
+                        _ => end.parse::<usize>().ok().map(|end| (start, Some(end))),
+                    }
+                });
+                match range {
+                    Some((start, Some(end))) if end < start => {
+                        Err(format!("{} is a range that ends before it starts", item))
+                    }
+                    Some((start, end)) => Ok(ColumnRange::Numbers(start, end)),
+                    None => Ok(ColumnRange::Column(ColumnSelector::parse(item))),
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the indexes of the columns among the given column names, or nothing if a named column isn't there.
+    pub fn resolve(&self, column_names: &[String]) -> Option<Vec<usize>> {
+        match self {
+            ColumnRange::Column(selector) => {
+                selector.resolve(column_names).map(|column| vec![column])
+            }
+            ColumnRange::Numbers(start, end) => {
+                Some((start - 1..end.unwrap_or(column_names.len())).collect())
+            }
+        }
+    }
+}
+
+impl Display for ColumnRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnRange::Column(selector) => write!(f, "{}", selector),
+            ColumnRange::Numbers(start, Some(end)) => write!(f, "{}-{}", start, end),
+            ColumnRange::Numbers(start, None) => write!(f, "{}-", start),
+        }
+    }
+}
+
+impl Display for ColumnSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnSelector::Name(name) => write!(f, "{}", name),
+            ColumnSelector::Number(number) => write!(f, "{}", number),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// Lists every file under a directory, including those in subdirectories, by its path relative to the directory.
+pub fn files_in(directory: &Path) -> io::Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    let mut directories = vec![PathBuf::new()];
+    while let Some(relative_directory) = directories.pop() {
+        for entry in fs::read_dir(directory.join(&relative_directory))? {
+            let entry = entry?;
+            let relative_path = relative_directory.join(entry.file_name());
+            if entry.path().is_dir() {
+                directories.push(relative_path);
+            } else {
+                files.insert(relative_path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexEntry {
+    path: String,
+    report: Option<String>,
+    num_problems: usize,
+    found_any_problems: bool,
+    error: Option<String>,
+}
+
+/// A summary of comparing two directories, linking to the report for each pair of files.
+#[derive(Debug, Serialize)]
+pub struct Index {
+    expected_directory: String,
+    actual_directory: String,
+    num_problems: usize,
+    num_files_with_problems: usize,
+    entries: Vec<IndexEntry>,
+    only_in_expected: Vec<String>,
+    only_in_actual: Vec<String>,
+}
+
+impl Index {
+    pub fn new(expected_directory: &Path, actual_directory: &Path) -> Self {
+        Index {
+            expected_directory: expected_directory.display().to_string(),
+            actual_directory: actual_directory.display().to_string(),
+            num_problems: 0,
+            num_files_with_problems: 0,
+            entries: vec![],
+            only_in_expected: vec![],
+            only_in_actual: vec![],
+        }
+    }
+
+    pub fn insert_compared(&mut self, path: &Path, report: &Path, num_problems: usize) {
+        self.num_problems += num_problems;
+        if num_problems > 0 {
+            self.num_files_with_problems += 1;
+        }
+        self.entries.push(IndexEntry {
+            path: path.display().to_string(),
+            // Links use forward slashes, whatever the platform.
+            report: Some(
+                report
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            ),
+            num_problems,
+            found_any_problems: num_problems > 0,
+            error: None,
+        });
+    }
+
+    pub fn insert_failed(&mut self, path: &Path, error: &str) {
+        self.num_files_with_problems += 1;
+        self.entries.push(IndexEntry {
+            path: path.display().to_string(),
+            report: None,
+            num_problems: 0,
+            found_any_problems: false,
+            error: Some(error.to_string()),
+        });
+    }
+
+    pub fn insert_only_in_expected(&mut self, path: &Path) {
+        self.num_files_with_problems += 1;
+        self.only_in_expected.push(path.display().to_string());
+    }
+
+    pub fn insert_only_in_actual(&mut self, path: &Path) {
+        self.num_files_with_problems += 1;
+        self.only_in_actual.push(path.display().to_string());
+    }
+}
@@ -0,0 +1,467 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use regex::Regex;
+
+use crate::columns::ColumnSelector;
+use crate::sources::{parse_date, SourceError};
+
+/// How a cell is compared with a value in a filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Equal => ordering == Ordering::Equal,
+            Self::NotEqual => ordering != Ordering::Equal,
+            Self::Less => ordering == Ordering::Less,
+            Self::LessOrEqual => ordering != Ordering::Greater,
+            Self::Greater => ordering == Ordering::Greater,
+            Self::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on the cells of a row, with its columns given either as picked out on the command line or by index.
+#[derive(Debug, Clone)]
+pub enum Condition<C> {
+    Compare(C, Operator, String),
+    Matches(C, Regex),
+    Not(Box<Condition<C>>),
+    And(Box<Condition<C>>, Box<Condition<C>>),
+    Or(Box<Condition<C>>, Box<Condition<C>>),
+}
+
+impl Condition<ColumnSelector> {
+    fn resolve(&self, column_names: &[String]) -> Result<Condition<usize>, SourceError> {
+        Ok(match self {
+            Self::Compare(column, operator, value) => Condition::Compare(
+                resolve_column(column, column_names)?,
+                *operator,
+                value.clone(),
+            ),
+            Self::Matches(column, pattern) => {
+                Condition::Matches(resolve_column(column, column_names)?, pattern.clone())
+            }
+            Self::Not(condition) => Condition::Not(Box::new(condition.resolve(column_names)?)),
+            Self::And(left, right) => Condition::And(
+                Box::new(left.resolve(column_names)?),
+                Box::new(right.resolve(column_names)?),
+            ),
+            Self::Or(left, right) => Condition::Or(
+                Box::new(left.resolve(column_names)?),
+                Box::new(right.resolve(column_names)?),
+            ),
+        })
+    }
+}
+
+fn resolve_column(column: &ColumnSelector, column_names: &[String]) -> Result<usize, SourceError> {
+    column
+        .resolve(column_names)
+        .ok_or_else(|| SourceError::MissingColumn(column.to_string()))
+}
+
+impl Condition<usize> {
+    /// Whether a record meets the condition.  A cell the record doesn't have is taken to be empty.
+    pub fn accepts(&self, record: &csv::StringRecord) -> bool {
+        match self {
+            Self::Compare(column, operator, value) => operator.accepts(compare_values(
+                record.get(*column).unwrap_or_default(),
+                value,
+            )),
+            Self::Matches(column, pattern) => {
+                pattern.is_match(record.get(*column).unwrap_or_default())
+            }
+            Self::Not(condition) => !condition.accepts(record),
+            Self::And(left, right) => left.accepts(record) && right.accepts(record),
+            Self::Or(left, right) => left.accepts(record) || right.accepts(record),
+        }
+    }
+}
+
+/// Reads a cell as a number if it is a finite one.  Text like `nan` or `inf` also reads as a number, but it has no
+/// place among the others, so it's compared as text.
+fn parse_finite(cell: &str) -> Option<f64> {
+    cell.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Orders a cell against a value as numbers if both are numbers, as dates if both are dates, and as text otherwise.
+fn compare_values(cell: &str, value: &str) -> Ordering {
+    if let (Some(cell), Some(value)) = (parse_finite(cell), parse_finite(value)) {
+        // Neither is NaN, so they are always ordered.
+        return cell.partial_cmp(&value).unwrap_or(Ordering::Equal);
+    }
+    if let (Some(cell), Some(value)) = (parse_date(cell), parse_date(value)) {
+        return cell.cmp(&value);
+    }
+    cell.cmp(value)
+}
+
+/// A filter that picks out which rows are compared, like `region = "North" and not (total < 100 or code ~ "^X")`.
+/// Columns are given by name or number, and names or values with spaces or symbols in them can be quoted.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expression: String,
+    condition: Condition<ColumnSelector>,
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?.into_iter().peekable(),
+        };
+        let condition = parser.parse_or()?;
+        match parser.tokens.next() {
+            None => Ok(Filter {
+                expression: expression.trim().to_string(),
+                condition,
+            }),
+            Some(token) => Err(format!("unexpected {} in the filter", token)),
+        }
+    }
+
+    /// Finds the columns the filter refers to among the given column names.
+    pub fn resolve(&self, column_names: &[String]) -> Result<Condition<usize>, SourceError> {
+        self.condition.resolve(column_names)
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Quoted(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 11] = ["(", ")", "==", "!=", "<=", ">=", "!~", "=", "<", ">", "~"];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            tokens.push(Token::Quoted(quoted(&mut chars, c)?));
+        } else if let Some(symbol) = SYMBOLS
+            .iter()
+            .find(|symbol| chars.clone().take(symbol.len()).eq(symbol.chars()))
+        {
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "()=!<>~\"'".contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            if word.is_empty() {
+                return Err(format!("unexpected {} in the filter", c));
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads quoted text up to its closing quote.  A backslash takes the next character as it is.
+fn quoted(chars: &mut Peekable<Chars>, quote: char) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(format!("missing closing {} in the filter", quote)),
+            Some(c) if c == quote => return Ok(text),
+            Some('\\') => text.extend(chars.next()),
+            Some(c) => text.push(c),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.tokens.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Condition<ColumnSelector>, String> {
+        let mut condition = self.parse_and()?;
+        while self.next_is_keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition<ColumnSelector>, String> {
+        let mut condition = self.parse_not()?;
+        while self.next_is_keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.parse_not()?));
+        }
+        Ok(condition)
+    }
+
+    fn parse_not(&mut self) -> Result<Condition<ColumnSelector>, String> {
+        if self.next_is_keyword("not") {
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+        if self.tokens.peek() == Some(&Token::Symbol("(")) {
+            self.tokens.next();
+            let condition = self.parse_or()?;
+            return match self.tokens.next() {
+                Some(Token::Symbol(")")) => Ok(condition),
+                _ => Err("missing closing ) in the filter".to_string()),
+            };
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Condition<ColumnSelector>, String> {
+        let column = match self.tokens.next() {
+            Some(Token::Word(word)) => ColumnSelector::parse(&word),
+            Some(Token::Quoted(name)) => ColumnSelector::Name(name),
+            Some(token) => {
+                return Err(format!(
+                    "expected a column but found {} in the filter",
+                    token
+                ))
+            }
+            None => return Err("the filter ends where a column was expected".to_string()),
+        };
+        let symbol = match self.tokens.next() {
+            Some(Token::Symbol(symbol)) if symbol != "(" && symbol != ")" => symbol,
+            _ => {
+                return Err(format!(
+                    "expected a comparison after {} in the filter",
+                    column
+                ))
+            }
+        };
+        let value = match self.tokens.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            _ => {
+                return Err(format!(
+                    "expected a value after {} {} in the filter",
+                    column, symbol
+                ))
+            }
+        };
+        let operator = match symbol {
+            "~" | "!~" => {
+                let pattern = Regex::new(&value).map_err(|error| error.to_string())?;
+                let condition = Condition::Matches(column, pattern);
+                return Ok(if symbol == "!~" {
+                    Condition::Not(Box::new(condition))
+                } else {
+                    condition
+                });
+            }
+            "=" | "==" => Operator::Equal,
+            "!=" => Operator::NotEqual,
+            "<" => Operator::Less,
+            "<=" => Operator::LessOrEqual,
+            ">" => Operator::Greater,
+            _ => Operator::GreaterOrEqual,
+        };
+        Ok(Condition::Compare(column, operator, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_names() -> Vec<String> {
+        vec!["region", "total", "code", "my name"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Which of the rows the filter accepts.
+    fn accepted(expression: &str, rows: &[&str]) -> Vec<bool> {
+        let condition = Filter::parse(expression)
+            .unwrap()
+            .resolve(&column_names())
+            .unwrap();
+        rows.iter()
+            .map(|row| condition.accepts(&row.split(',').collect::<csv::StringRecord>()))
+            .collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let rows = ["North,50,A", "South,50,A", "South,500,A", "North,500,A"];
+        assert_eq!(
+            accepted("region = North or total > 100 and code = B", &rows),
+            vec![true, false, false, true]
+        );
+        assert_eq!(
+            accepted("(region = North or total > 100) and code = A", &rows),
+            vec![true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let rows = [
+            "North,50,X1",
+            "North,500,X1",
+            "North,500,Y1",
+            "South,500,Y1",
+        ];
+        assert_eq!(
+            accepted("not total < 100 and not code ~ '^X'", &rows),
+            vec![false, false, true, true]
+        );
+        assert_eq!(
+            accepted(
+                "region = North and not (total < 100 or code ~ \"^X\")",
+                &rows
+            ),
+            vec![false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        assert_eq!(
+            accepted(
+                "NOT region = North AND total >= 50",
+                &["North,50", "South,50", "South,5"]
+            ),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn compares_numbers_dates_and_text() {
+        assert_eq!(
+            accepted("total < 100", &["a,99", "a,100.0", "a,1e3"]),
+            vec![true, false, false]
+        );
+        assert_eq!(
+            accepted("total >= 2024-02-01", &["a,2024-01-31", "a,2024-02-01"]),
+            vec![false, true]
+        );
+        assert_eq!(
+            accepted("region > M", &["North", "East"]),
+            vec![true, false]
+        );
+        assert_eq!(
+            accepted("total > 100", &["a,NaN", "a,inf", "a,200"]),
+            vec![true, true, true]
+        );
+        assert_eq!(
+            accepted("total <= 100", &["a,NaN", "a,inf", "a,50"]),
+            vec![false, false, true]
+        );
+        assert_eq!(
+            accepted("total = 0", &["a,NaN", "a,0.0", "a,-0"]),
+            vec![false, true, true]
+        );
+        assert_eq!(
+            accepted("code != ''", &["a,1,", "a,1,x", "a,1"]),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn quotes_names_and_values() {
+        let rows = ["a,1,x,Ann Lee", "a,1,x,Bob", "a,1,x,it's"];
+        assert_eq!(
+            accepted("'my name' = \"Ann Lee\"", &rows),
+            vec![true, false, false]
+        );
+        assert_eq!(
+            accepted("\"my name\" == 'it\\'s'", &rows),
+            vec![false, false, true]
+        );
+        assert_eq!(accepted("4 !~ ' '", &rows), vec![false, true, true]);
+    }
+
+    #[test]
+    fn keeps_operator_symbols_in_quotes() {
+        assert_eq!(
+            accepted("code = 'a=b (c)'", &["x,1,a=b (c)", "x,1,a=b"]),
+            vec![true, false]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_filters() {
+        let error = |expression| Filter::parse(expression).unwrap_err();
+        assert_eq!(error("region = 'North"), "missing closing ' in the filter");
+        assert_eq!(error("(region = North"), "missing closing ) in the filter");
+        assert_eq!(error("region = North )"), "unexpected ) in the filter");
+        assert_eq!(
+            error("region North"),
+            "expected a comparison after region in the filter"
+        );
+        assert_eq!(
+            error("region ="),
+            "expected a value after region = in the filter"
+        );
+        assert_eq!(
+            error("region = North and"),
+            "the filter ends where a column was expected"
+        );
+        assert_eq!(
+            error("= North"),
+            "expected a column but found = in the filter"
+        );
+        assert!(error("code ~ '('").contains("regex parse error"));
+    }
+
+    #[test]
+    fn reports_unknown_columns() {
+        let filter = Filter::parse("region = North and missing = 1").unwrap();
+        match filter.resolve(&column_names()) {
+            Err(SourceError::MissingColumn(column)) => assert_eq!(column, "missing"),
+            _ => panic!("the missing column wasn't reported"),
+        }
+    }
+}
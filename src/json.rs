@@ -0,0 +1,245 @@
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::sources::canonical_number;
+
+/// How two cells compared when read as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonComparison {
+    Same,
+    /// The cells differ, first at this path, like `$.items[2].name`.
+    Different(String),
+    /// One of the cells isn't JSON, so they can only be compared as text.
+    NotJson,
+}
+
+/// Compares two cells as JSON, so that the order of keys and the way numbers are written don't matter.
+pub fn compare(expected: &str, actual: &str) -> JsonComparison {
+    match (
+        serde_json::from_str::<Value>(expected),
+        serde_json::from_str::<Value>(actual),
+    ) {
+        (Ok(expected), Ok(actual)) => {
+            match first_difference(&expected, &actual, &mut String::from("$")) {
+                Some(path) => JsonComparison::Different(path),
+                None => JsonComparison::Same,
+            }
+        }
+        _ => JsonComparison::NotJson,
+    }
+}
+
+/// Writes a cell that's JSON the same way as every cell it's the same as, with the keys of objects in order and numbers
+/// written the same way.
+pub fn canonical(cell: &str) -> Option<String> {
+    let value = serde_json::from_str::<Value>(cell).ok()?;
+    let mut canonical = String::new();
+    write_canonical(&value, &mut canonical);
+    Some(canonical)
+}
+
+fn write_canonical(value: &Value, canonical: &mut String) {
+    match value {
+        Value::Number(number) => {
+            let number = number.to_string();
+            canonical.push_str(&canonical_number(&number).unwrap_or(number));
+        }
+        Value::Array(values) => {
+            canonical.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    canonical.push(',');
+                }
+                write_canonical(value, canonical);
+            }
+            canonical.push(']');
+        }
+        Value::Object(object) => {
+            canonical.push('{');
+            for (index, key) in object.keys().sorted().enumerate() {
+                if index > 0 {
+                    canonical.push(',');
+                }
+                canonical.push_str(&Value::String(key.clone()).to_string());
+                canonical.push(':');
+                write_canonical(&object[key], canonical);
+            }
+            canonical.push('}');
+        }
+        other => canonical.push_str(&other.to_string()),
+    }
+}
+
+fn numbers_match(expected: &serde_json::Number, actual: &serde_json::Number) -> bool {
+    match (expected.as_i64(), actual.as_i64()) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => match (expected.as_u64(), actual.as_u64()) {
+            (Some(expected), Some(actual)) => expected == actual,
+            _ => expected.as_f64() == actual.as_f64(),
+        },
+    }
+}
+
+/// Adds an object key to a path, quoting it unless it's a plain name.
+fn push_key(path: &mut String, key: &str) {
+    let is_plain = key
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && key
+            .chars()
+            .all(|character| character.is_alphanumeric() || character == '_');
+    if is_plain {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push('[');
+        path.push_str(&Value::String(key.to_string()).to_string());
+        path.push(']');
+    }
+}
+
+fn first_difference(expected: &Value, actual: &Value, path: &mut String) -> Option<String> {
+    let path_len = path.len();
+    match (expected, actual) {
+        (Value::Number(expected), Value::Number(actual)) => {
+            if numbers_match(expected, actual) {
+                None
+            } else {
+                Some(path.clone())
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            let mut difference = None;
+            for index in 0..expected.len().max(actual.len()) {
+                path.push_str(&format!("[{}]", index));
+                difference = match (expected.get(index), actual.get(index)) {
+                    (Some(expected), Some(actual)) => first_difference(expected, actual, path),
+                    _ => Some(path.clone()),
+                };
+                path.truncate(path_len);
+                if difference.is_some() {
+                    break;
+                }
+            }
+            difference
+        }
+        (Value::Object(expected), Value::Object(actual)) => {
+            let mut difference = None;
+            // Keys are checked in the order the expected value has them, then any only the actual value has.
+            let keys = expected
+                .keys()
+                .chain(actual.keys().filter(|key| !expected.contains_key(*key)));
+            for key in keys {
+                push_key(path, key);
+                difference = match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => first_difference(expected, actual, path),
+                    _ => Some(path.clone()),
+                };
+                path.truncate(path_len);
+                if difference.is_some() {
+                    break;
+                }
+            }
+            difference
+        }
+        (expected, actual) => {
+            if expected == actual {
+                None
+            } else {
+                Some(path.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn different(path: &str) -> JsonComparison {
+        JsonComparison::Different(path.to_string())
+    }
+
+    #[test]
+    fn ignores_key_order() {
+        assert_eq!(
+            compare(
+                r#"{"a": 1, "b": {"c": 2, "d": 3}}"#,
+                r#"{"b": {"d": 3, "c": 2}, "a": 1}"#
+            ),
+            JsonComparison::Same
+        );
+        assert_eq!(
+            compare(r#"{"a": 1, "b": 2}"#, r#"{"b": 3, "a": 1}"#),
+            different("$.b")
+        );
+    }
+
+    #[test]
+    fn reports_keys_only_one_side_has() {
+        assert_eq!(
+            compare(r#"{"a": 1, "b": 2}"#, r#"{"a": 1}"#),
+            different("$.b")
+        );
+        assert_eq!(
+            compare(r#"{"a": 1}"#, r#"{"a": 1, "first name": 2}"#),
+            different(r#"$["first name"]"#)
+        );
+    }
+
+    #[test]
+    fn ignores_how_numbers_are_written() {
+        assert_eq!(
+            compare("[1, 2.5, 300]", "[1.0, 2.50, 3e2]"),
+            JsonComparison::Same
+        );
+        assert_eq!(
+            compare("18446744073709551615", "18446744073709551615"),
+            JsonComparison::Same
+        );
+        assert_eq!(compare("[1, 2]", "[1, 2.01]"), different("$[1]"));
+        assert_eq!(compare("[1, 2]", r#"[1, "2"]"#), different("$[1]"));
+    }
+
+    #[test]
+    fn reports_the_first_index_past_the_shorter_array() {
+        assert_eq!(
+            compare(r#"{"items": [1, 2]}"#, r#"{"items": [1, 2, 3]}"#),
+            different("$.items[2]")
+        );
+        assert_eq!(
+            compare(
+                r#"{"items": [{"n": 1}, {"n": 2}]}"#,
+                r#"{"items": [{"n": 1}]}"#
+            ),
+            different("$.items[1]")
+        );
+        assert_eq!(
+            compare(
+                r#"{"items": [{"n": 1}, {"n": 2}]}"#,
+                r#"{"items": [{"n": 1}, {"n": 4}]}"#
+            ),
+            different("$.items[1].n")
+        );
+    }
+
+    #[test]
+    fn compares_cells_that_are_not_json_as_text() {
+        assert_eq!(compare("{\"a\": 1}", "{a: 1}"), JsonComparison::NotJson);
+        assert_eq!(compare("", "1"), JsonComparison::NotJson);
+    }
+
+    #[test]
+    fn writes_cells_that_are_the_same_the_same_way() {
+        assert_eq!(
+            canonical(r#"{"b": [1.0, 2e1], "a": {"d": null, "c": "x"}}"#),
+            canonical(r#"{"a": {"c": "x", "d": null}, "b": [1, 20]}"#)
+        );
+        assert_eq!(
+            canonical(r#"{"b": 1, "a": 2.5}"#),
+            Some(r#"{"a":2.5,"b":1}"#.to_string())
+        );
+        assert_eq!(canonical("{a: 1}"), None);
+    }
+}
@@ -0,0 +1,462 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::mem::size_of;
+use std::path::Path;
+
+use clap::arg_enum;
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::sources::SourceError;
+
+/// About how many bytes of records are sorted in memory at a time.  Larger files are sorted in runs written to
+/// temporary files, which are then merged, so memory use doesn't grow with the size of the file.
+const RUN_BYTES: usize = 64 * 1024 * 1024;
+
+/// How many runs are merged at a time, which bounds how many temporary files are open at once.
+const MAX_MERGED_RUNS: usize = 64;
+
+arg_enum! {
+    /// How rows that share a key are paired up: only the first row with the key in each file, in the order the rows
+    /// appear in each file, or not at all, in which case only the duplicate keys are reported.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum DuplicateKeys {
+        First,
+        Positional,
+        Fail
+    }
+}
+
+// `arg_enum!` doesn't allow attributes on variants, so the default can't be derived.
+#[allow(clippy::derivable_impls)]
+impl Default for DuplicateKeys {
+    fn default() -> Self {
+        DuplicateKeys::Positional
+    }
+}
+
+/// A record along with its key and the number of the record in its file.
+#[derive(Debug, Clone)]
+pub struct KeyedRecord {
+    pub key: Vec<String>,
+    pub line: usize,
+    pub record: csv::StringRecord,
+}
+
+impl KeyedRecord {
+    /// Records are ordered by key, then by where they were in the file, so records with the same key keep their order.
+    fn sort_key(&self) -> (&[String], usize) {
+        (&self.key, self.line)
+    }
+
+    /// Roughly how much memory the record takes up.
+    fn size(&self) -> usize {
+        size_of::<Self>()
+            + self.record.as_slice().len()
+            + self.record.len() * size_of::<usize>()
+            + self
+                .key
+                .iter()
+                .map(|cell| cell.len() + size_of::<String>())
+                .sum::<usize>()
+    }
+}
+
+impl PartialEq for KeyedRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.sort_key() == other.sort_key()
+    }
+}
+
+impl Eq for KeyedRecord {}
+
+impl PartialOrd for KeyedRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KeyedRecord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+/// A sorted run of records written to a temporary file.  Each row holds the record number, where the record started
+/// in its file, then the record's cells.  The file is closed until the run is merged, so that only the runs being
+/// merged are open at a time.
+struct Run {
+    path: TempPath,
+}
+
+impl Run {
+    fn write(
+        temp_dir: &Path,
+        records: impl Iterator<Item = Result<KeyedRecord, SourceError>>,
+    ) -> Result<Self, SourceError> {
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(NamedTempFile::new_in(temp_dir)?);
+        for keyed in records {
+            let keyed = keyed?;
+            let position = keyed.record.position();
+            let mut row = csv::StringRecord::new();
+            row.push_field(&keyed.line.to_string());
+            row.push_field(
+                &position
+                    .map(|position| position.line().to_string())
+                    .unwrap_or_default(),
+            );
+            row.push_field(
+                &position
+                    .map(|position| position.byte().to_string())
+                    .unwrap_or_default(),
+            );
+            row.extend(keyed.record.iter());
+            writer.write_record(&row)?;
+        }
+        let file = writer
+            .into_inner()
+            .map_err(|error| SourceError::IO(error.into_error()))?;
+        Ok(Run {
+            path: file.into_temp_path(),
+        })
+    }
+
+    fn open(&self) -> Result<RunReader, SourceError> {
+        Ok(RunReader {
+            reader: csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(&self.path)?,
+        })
+    }
+}
+
+struct RunReader {
+    reader: csv::Reader<File>,
+}
+
+impl RunReader {
+    fn next(&mut self) -> Option<Result<(usize, csv::StringRecord), SourceError>> {
+        let mut row = csv::StringRecord::new();
+        match self.reader.read_record(&mut row) {
+            Ok(false) => None,
+            Err(error) => Some(Err(error.into())),
+            Ok(true) => {
+                let number =
+                    |field: usize| row.get(field).and_then(|value| value.parse::<u64>().ok());
+                let line = number(0).unwrap_or_default() as usize;
+                let mut record: csv::StringRecord = row.iter().skip(3).collect();
+                if let (Some(physical_line), Some(byte)) = (number(1), number(2)) {
+                    let mut position = csv::Position::new();
+                    position
+                        .set_line(physical_line)
+                        .set_byte(byte)
+                        .set_record(line as u64);
+                    record.set_position(Some(position));
+                }
+                Some(Ok((line, record)))
+            }
+        }
+    }
+}
+
+/// The records of several sorted runs, merged into one sorted sequence.  The runs are deleted once the merge is done
+/// with them.
+struct Merge<K> {
+    key: K,
+    readers: Vec<RunReader>,
+    /// Kept so the runs' files are only deleted after their readers are closed.
+    _runs: Vec<Run>,
+    heap: BinaryHeap<Reverse<(KeyedRecord, usize)>>,
+}
+
+impl<K> Merge<K>
+where
+    K: Fn(&csv::StringRecord) -> Vec<String>,
+{
+    fn new(key: K, runs: Vec<Run>) -> Result<Self, SourceError> {
+        let readers = runs.iter().map(Run::open).collect::<Result<_, _>>()?;
+        let mut merge = Merge {
+            key,
+            readers,
+            _runs: runs,
+            heap: BinaryHeap::new(),
+        };
+        for index in 0..merge.readers.len() {
+            merge.refill(index)?;
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next record of a run onto the heap.
+    fn refill(&mut self, index: usize) -> Result<(), SourceError> {
+        if let Some(next) = self.readers[index].next() {
+            let (line, record) = next?;
+            self.heap.push(Reverse((
+                KeyedRecord {
+                    key: (self.key)(&record),
+                    line,
+                    record,
+                },
+                index,
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<K> Iterator for Merge<K>
+where
+    K: Fn(&csv::StringRecord) -> Vec<String>,
+{
+    type Item = Result<KeyedRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((keyed, index)) = self.heap.pop()?;
+        match self.refill(index) {
+            Ok(()) => Some(Ok(keyed)),
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// The records of a file in order of their keys, either straight from memory or merged from sorted runs on disk.
+pub struct SortedRecords<K> {
+    in_memory: std::vec::IntoIter<KeyedRecord>,
+    merge: Option<Merge<K>>,
+}
+
+impl<K> SortedRecords<K>
+where
+    K: Fn(&csv::StringRecord) -> Vec<String>,
+{
+    /// Sorts records by the key the given function picks out of each one, writing runs of them to `temp_dir` if there
+    /// are too many to sort in memory.
+    pub fn sort(
+        records: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        key: K,
+        temp_dir: &Path,
+    ) -> Result<Self, SourceError> {
+        Self::sort_with_limits(records, key, temp_dir, RUN_BYTES, MAX_MERGED_RUNS)
+    }
+
+    /// Sorts records in runs of about `run_bytes` bytes, merging at most `max_merged_runs` runs at a time.
+    fn sort_with_limits(
+        records: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        key: K,
+        temp_dir: &Path,
+        run_bytes: usize,
+        max_merged_runs: usize,
+    ) -> Result<Self, SourceError> {
+        let mut runs = vec![];
+        let mut run = vec![];
+        let mut bytes = 0;
+        for (line, record) in (1..).zip(records) {
+            let record = record?;
+            let keyed = KeyedRecord {
+                key: key(&record),
+                line,
+                record,
+            };
+            bytes += keyed.size();
+            run.push(keyed);
+            if bytes >= run_bytes {
+                run.sort_unstable();
+                runs.push(Run::write(temp_dir, run.drain(..).map(Ok))?);
+                bytes = 0;
+            }
+        }
+        run.sort_unstable();
+        if runs.is_empty() {
+            return Ok(SortedRecords {
+                in_memory: run.into_iter(),
+                merge: None,
+            });
+        }
+        if !run.is_empty() {
+            runs.push(Run::write(temp_dir, run.into_iter().map(Ok))?);
+        }
+
+        // Merging every run at once would need a file open for each of them, so runs are merged into fewer, longer
+        // runs until few enough are left.
+        let max_merged_runs = max_merged_runs.max(2);
+        while runs.len() > max_merged_runs {
+            let mut merged_runs = vec![];
+            let mut remaining = runs.into_iter().peekable();
+            while remaining.peek().is_some() {
+                let mut group: Vec<Run> = remaining.by_ref().take(max_merged_runs).collect();
+                if group.len() == 1 {
+                    merged_runs.append(&mut group);
+                } else {
+                    merged_runs.push(Run::write(temp_dir, Merge::new(&key, group)?)?);
+                }
+            }
+            runs = merged_runs;
+        }
+        Ok(SortedRecords {
+            in_memory: vec![].into_iter(),
+            merge: Some(Merge::new(key, runs)?),
+        })
+    }
+}
+
+impl<K> Iterator for SortedRecords<K>
+where
+    K: Fn(&csv::StringRecord) -> Vec<String>,
+{
+    type Item = Result<KeyedRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.merge {
+            Some(merge) => merge.next(),
+            None => self.in_memory.next().map(Ok),
+        }
+    }
+}
+
+/// Gathers sorted records into groups of records that share a key.
+pub struct KeyGroups<I> {
+    records: I,
+    next: Option<KeyedRecord>,
+}
+
+impl<I> KeyGroups<I>
+where
+    I: Iterator<Item = Result<KeyedRecord, SourceError>>,
+{
+    pub fn new(records: I) -> Self {
+        KeyGroups {
+            records,
+            next: None,
+        }
+    }
+}
+
+impl<I> Iterator for KeyGroups<I>
+where
+    I: Iterator<Item = Result<KeyedRecord, SourceError>>,
+{
+    type Item = Result<Vec<KeyedRecord>, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.next.take() {
+            Some(keyed) => keyed,
+            None => match self.records.next()? {
+                Ok(keyed) => keyed,
+                Err(error) => return Some(Err(error)),
+            },
+        };
+        let mut group = vec![first];
+        loop {
+            match self.records.next() {
+                None => break,
+                Some(Err(error)) => return Some(Err(error)),
+                Some(Ok(keyed)) if keyed.key == group[0].key => group.push(keyed),
+                Some(Ok(keyed)) => {
+                    self.next = Some(keyed);
+                    break;
+                }
+            }
+        }
+        Some(Ok(group))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(keys: &[&str]) -> Vec<Result<csv::StringRecord, SourceError>> {
+        keys.iter()
+            .enumerate()
+            .map(|(index, key)| {
+                Ok(csv::StringRecord::from(vec![
+                    key.to_string(),
+                    index.to_string(),
+                ]))
+            })
+            .collect()
+    }
+
+    fn first_cell(record: &csv::StringRecord) -> Vec<String> {
+        vec![record.get(0).unwrap_or_default().to_string()]
+    }
+
+    fn sorted(keys: &[&str], run_bytes: usize, max_merged_runs: usize) -> Vec<(String, usize)> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        SortedRecords::sort_with_limits(
+            records(keys).into_iter(),
+            first_cell,
+            temp_dir.path(),
+            run_bytes,
+            max_merged_runs,
+        )
+        .unwrap()
+        .map(|keyed| {
+            let keyed = keyed.unwrap();
+            (keyed.key[0].clone(), keyed.line)
+        })
+        .collect()
+    }
+
+    const KEYS: [&str; 10] = ["d", "b", "a", "c", "b", "e", "a", "d", "c", "b"];
+
+    fn expected() -> Vec<(String, usize)> {
+        let mut expected: Vec<(String, usize)> = (1..)
+            .zip(KEYS.iter())
+            .map(|(line, key)| (key.to_string(), line))
+            .collect();
+        expected.sort();
+        expected
+    }
+
+    #[test]
+    fn sorts_in_memory() {
+        assert_eq!(sorted(&KEYS, usize::MAX, 2), expected());
+    }
+
+    #[test]
+    fn merges_runs_in_one_pass() {
+        // Every record is its own run.
+        assert_eq!(sorted(&KEYS, 1, KEYS.len()), expected());
+    }
+
+    #[test]
+    fn merges_runs_in_several_passes() {
+        assert_eq!(sorted(&KEYS, 1, 2), expected());
+        assert_eq!(sorted(&KEYS, 1, 3), expected());
+    }
+
+    #[test]
+    fn keeps_positions_through_runs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut record = csv::StringRecord::from(vec!["a"]);
+        let mut position = csv::Position::new();
+        position.set_line(7).set_byte(42);
+        record.set_position(Some(position));
+        let records = vec![Ok(csv::StringRecord::from(vec!["b"])), Ok(record)];
+        let sorted: Vec<KeyedRecord> =
+            SortedRecords::sort_with_limits(records.into_iter(), first_cell, temp_dir.path(), 1, 2)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+        let position = sorted[0].record.position().unwrap();
+        assert_eq!((position.line(), position.byte()), (7, 42));
+        assert_eq!(sorted[0].line, 2);
+    }
+
+    #[test]
+    fn groups_records_by_key() {
+        let groups: Vec<Vec<usize>> = KeyGroups::new(
+            SortedRecords::sort(records(&KEYS).into_iter(), first_cell, Path::new(".")).unwrap(),
+        )
+        .map(|group| group.unwrap().iter().map(|keyed| keyed.line).collect())
+        .collect();
+        assert_eq!(
+            groups,
+            vec![vec![3, 7], vec![2, 5, 10], vec![4, 9], vec![1, 8], vec![6]]
+        );
+    }
+}
@@ -1,44 +1,90 @@
 extern crate clap;
 extern crate csv;
+extern crate glob;
 extern crate handlebars;
 extern crate itertools;
+extern crate parquet;
+extern crate regex;
 extern crate serde;
 extern crate serde_json;
+extern crate similar;
+extern crate tempfile;
+extern crate unicode_normalization;
 
+mod align;
+mod baseline;
+mod cell_diff;
+mod cells;
+mod columns;
+mod directories;
+mod filter;
+mod json;
+mod keyed;
+mod normalize;
+mod patch;
 mod problems;
+mod rewrite;
+mod sources;
+mod three_way;
+mod unordered;
 
+use std::cmp::{max, Ordering};
 use std::env;
-use std::fmt::Debug;
-use std::fs::File;
-use std::io::{self, Error, Write};
-use std::path::Path;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Error, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::time::SystemTime;
 
-use clap::{arg_enum, value_t, App, Arg};
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 use handlebars::{Handlebars, RenderError, TemplateError};
 use itertools::{EitherOrBoth, Itertools};
+use regex::bytes::Regex;
+use serde::Serialize;
 
-use problems::Problems;
-
-
-arg_enum! {
-    #[derive(PartialEq, Debug)]
-    enum Delimiter {
-        Comma,
-        Pipe,
-        Tab
-    }
-}
+use align::Alignment;
+use cell_diff::Change;
+use cells::{CellComparison, ColumnComparison, Nulls};
+use columns::{ColumnRange, ColumnSelector};
+use directories::Index;
+use filter::Filter;
+use json::JsonComparison;
+use keyed::{DuplicateKeys, KeyGroups, KeyedRecord, SortedRecords};
+use normalize::{Normalization, Normalizations};
+use patch::{Patch, RowAddress};
+use problems::{DisplayProblems, FilterSummary, Positions, Problems, RowProblem, Section};
+use rewrite::Rewrite;
+use sources::{
+    get_source, ColumnType, Delimiter, FilteredSource, Format, RecordedSource, SkipOptions, Source,
+    SourceError, SourceOptions, SqliteQuery,
+};
+use three_way::ThreeWayComparison;
 
 const DEFAULT_MAX_PROBLEMS: usize = 5000;
+const DEFAULT_SIMILARITY: f64 = 0.5;
+/// How many records of the expected file are read to guess the type of each column.
+const INFERENCE_SAMPLE_SIZE: usize = 1000;
 const REPORT_TEMPLATE: &str = include_str!("../resources/report.html");
+const INDEX_TEMPLATE: &str = include_str!("../resources/index.html");
+const COMBINED_TEMPLATE: &str = include_str!("../resources/combined.html");
+const THREE_WAY_TEMPLATE: &str = include_str!("../resources/three_way.html");
 
 #[derive(Debug)]
 enum ReportError {
     IO(io::Error),
     Render(RenderError),
-    Template(TemplateError),
+    Template(Box<TemplateError>),
+}
+
+impl Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IO(error) => write!(f, "{}", error),
+            Self::Render(error) => write!(f, "{}", error),
+            Self::Template(error) => write!(f, "{}", error),
+        }
+    }
 }
 
 impl From<io::Error> for ReportError {
@@ -55,118 +101,710 @@ impl From<RenderError> for ReportError {
 
 impl From<TemplateError> for ReportError {
     fn from(error: TemplateError) -> Self {
-        ReportError::Template(error)
+        ReportError::Template(Box::new(error))
     }
 }
 
 fn generate_report<P: AsRef<Path>>(
-    problems: &Problems,
-    actual_filepath: &str,
+    comparison: &Comparison,
+    actual_filepath: &Path,
     report_filepath: P,
 ) -> Result<(), ReportError> {
     let mut registry = Handlebars::new();
     registry.register_template_string("report", REPORT_TEMPLATE)?;
-    let report_contents = registry.render("report", &problems.display_data(actual_filepath))?;
+    let report_contents = registry.render(
+        "report",
+        &comparison.problems.display_data(
+            &actual_filepath.display().to_string(),
+            comparison.expected_sheet.as_deref(),
+            comparison.actual_sheet.as_deref(),
+        ),
+    )?;
+    let mut report_file = File::create(report_filepath)?;
+    report_file.write_all(report_contents.as_bytes())?;
+    Ok(())
+}
+
+fn generate_combined_report<P: AsRef<Path>>(
+    report: &CombinedReport,
+    report_filepath: P,
+) -> Result<(), ReportError> {
+    let mut registry = Handlebars::new();
+    registry.register_template_string("combined", COMBINED_TEMPLATE)?;
+    let report_contents = registry.render("combined", report)?;
+    let mut report_file = File::create(report_filepath)?;
+    report_file.write_all(report_contents.as_bytes())?;
+    Ok(())
+}
+
+fn generate_three_way_report<P: AsRef<Path>>(
+    comparison: &ThreeWayComparison,
+    report_filepath: P,
+) -> Result<(), ReportError> {
+    let mut registry = Handlebars::new();
+    registry.register_template_string("three_way", THREE_WAY_TEMPLATE)?;
+    let report_contents = registry.render("three_way", comparison)?;
     let mut report_file = File::create(report_filepath)?;
     report_file.write_all(report_contents.as_bytes())?;
     Ok(())
 }
 
+fn generate_index<P: AsRef<Path>>(index: &Index, index_filepath: P) -> Result<(), ReportError> {
+    let mut registry = Handlebars::new();
+    registry.register_template_string("index", INDEX_TEMPLATE)?;
+    let index_contents = registry.render("index", index)?;
+    let mut index_file = File::create(index_filepath)?;
+    index_file.write_all(index_contents.as_bytes())?;
+    Ok(())
+}
+
+/// Settings for how cells are compared, which apply to every pair of files in a run.
+#[derive(Debug, Clone, Default)]
+struct CompareOptions {
+    normalizations: Normalizations,
+    nulls: Nulls,
+    rewrites: Vec<Rewrite>,
+    json_columns: Vec<ColumnSelector>,
+    columns: Vec<ColumnRange>,
+    filter: Option<Filter>,
+    write_patch: bool,
+    infer_types: bool,
+    unordered: bool,
+    align: bool,
+    similarity: f64,
+    key_columns: Vec<ColumnSelector>,
+    duplicate_keys: DuplicateKeys,
+    /// Where temporary files go when a file is too large to compare in memory.
+    temp_dir: PathBuf,
+}
+
+/// Writes the problems to the terminal, marking the parts of mismatched cells that were removed and added as
+/// `[-removed-]` and `{+added+}`, in color when the output is a terminal.  Stops quietly if the output is closed,
+/// such as when it is piped into `head`.
+fn print_problems(actual_filename: &str, problems: &Problems) {
+    let _ = write_problems(&mut io::stdout().lock(), actual_filename, problems);
+}
+
+fn print_three_way(comparison: &ThreeWayComparison) {
+    let _ = write_three_way(&mut io::stdout().lock(), comparison);
+}
+
+fn write_three_way(out: &mut io::StdoutLock, comparison: &ThreeWayComparison) -> io::Result<()> {
+    let counts = &comparison.counts;
+    writeln!(
+        out,
+        "{}: {} conflicting cells, {} changed in ours, {} changed in theirs, {} changed identically, {} unchanged",
+        comparison.theirs_filename,
+        counts.conflicting,
+        counts.changed_in_ours,
+        counts.changed_in_theirs,
+        counts.changed_identically,
+        counts.unchanged
+    )?;
+    for cell in comparison.conflicts.iter().chain(&comparison.changes) {
+        writeln!(out, "  {}", cell)?;
+    }
+    Ok(())
+}
+
+fn write_problems(
+    out: &mut io::StdoutLock,
+    actual_filename: &str,
+    problems: &Problems,
+) -> io::Result<()> {
+    let in_color = out.is_terminal();
+    let (removed_style, added_style, reset) = if in_color {
+        ("\x1b[31m", "\x1b[32m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    writeln!(out, "{}: {} problems", actual_filename, problems.len())?;
+    if let Some(baseline) = problems.baseline_summary() {
+        writeln!(
+            out,
+            "  Left out {} problems approved in the baseline.",
+            baseline.num_approved
+        )?;
+        for entry in &baseline.no_longer_found {
+            writeln!(out, "  No longer found: {}", entry)?;
+        }
+    }
+    if let Some(filter) = problems.filter_summary() {
+        writeln!(
+            out,
+            "  Only rows where {} were compared, leaving out {} rows of the expected file and {} of the actual file.",
+            filter.expression, filter.expected_excluded, filter.actual_excluded
+        )?;
+    }
+    for problem in problems.displayable_problems() {
+        let (problem_type, _, description) = problem.describe();
+        writeln!(out, "  {}: {}", problem_type, description)?;
+        if let Some(cell_diff) = problem.cell_diff() {
+            let marked = cell_diff
+                .iter()
+                .map(|segment| match segment.change {
+                    Change::Same => segment.text.clone(),
+                    Change::Removed => format!("{}[-{}-]{}", removed_style, segment.text, reset),
+                    Change::Added => format!("{}{{+{}+}}{}", added_style, segment.text, reset),
+                })
+                .join("");
+            writeln!(out, "    {}", marked)?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 struct Summary {
     problems: Problems,
-    errors: Vec<csv::Error>,
-    max_problems: usize,
+    errors: Vec<SourceError>,
+    options: CompareOptions,
+    /// The edits that turn the actual file into the expected one, when a patch was asked for.
+    patch: Option<Patch>,
 }
 
 impl Summary {
-    fn new(max_problems: Option<usize>) -> Summary {
+    fn new(max_problems: Option<usize>, options: &CompareOptions) -> Summary {
         Summary {
             problems: Problems::new(max_problems.unwrap_or(DEFAULT_MAX_PROBLEMS)),
             errors: vec![],
-            max_problems: max_problems.unwrap_or(DEFAULT_MAX_PROBLEMS),
+            options: options.clone(),
+            patch: if options.write_patch {
+                Some(Patch::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Adds the updates that turn a row of the actual file into the expected row it was paired with.  Every cell that is
+    /// written differently is updated, even when the comparison doesn't count the difference, so that the patched file
+    /// is the expected file.  A row with a different number of cells is replaced instead.
+    fn patch_pair(
+        &mut self,
+        row: RowAddress,
+        expected_record: &csv::StringRecord,
+        actual_record: &csv::StringRecord,
+    ) {
+        let patch = match &mut self.patch {
+            Some(patch) => patch,
+            None => return,
+        };
+        if expected_record.len() != actual_record.len() {
+            let before = match row {
+                RowAddress::Line(line) => Some(line + 1),
+                RowAddress::Key(_) => None,
+            };
+            patch.delete(row, actual_record);
+            patch.insert(before, expected_record);
+            return;
+        }
+        for (column, (expected, actual)) in expected_record.iter().zip(actual_record).enumerate() {
+            if expected != actual {
+                patch.update(row.clone(), column + 1, actual, expected);
+            }
         }
     }
 
     fn compare_line(
         &mut self,
+        section: Section,
         line_number: usize,
         expected_line: &csv::StringRecord,
         actual_line: &csv::StringRecord,
+        columns: &[ColumnComparison],
+        key: Option<String>,
     ) {
-        let mut column_number = 1;
-
-        for cells in expected_line.iter().zip_longest(actual_line.iter()) {
+        let positions = Positions {
+            expected: expected_line.position().cloned(),
+            actual: actual_line.position().cloned(),
+            key,
+        };
+        for (column_number, cells) in
+            (1..).zip(expected_line.iter().zip_longest(actual_line.iter()))
+        {
+            let compared = match columns.get(column_number - 1) {
+                Some(column) => column.compared,
+                None => self.options.columns.is_empty(),
+            };
+            if !compared {
+                continue;
+            }
             match cells {
                 EitherOrBoth::Both(expected, actual) => {
-                    if expected != actual {
-                        self.problems
-                            .insert_line_problem(problems::LineProblem::MismatchedCell {
-                                line: line_number,
-                                column: column_number,
-                                expected: expected.to_string(),
-                                actual: actual.to_string(),
-                            });
-                    }
+                    let default_column;
+                    let column = match columns.get(column_number - 1) {
+                        Some(column) => column,
+                        None => {
+                            default_column = ColumnComparison {
+                                column_type: ColumnType::Text,
+                                checks_type: false,
+                                rewrites: vec![],
+                                normalization: self.options.normalizations.all_columns,
+                                compared: self.options.columns.is_empty(),
+                            };
+                            &default_column
+                        }
+                    };
+                    let problem = match column.compare(expected, actual, &self.options.nulls) {
+                        CellComparison::Same => continue,
+                        CellComparison::Different => problems::LineProblem::MismatchedCell {
+                            line: line_number,
+                            positions: positions.clone(),
+                            column: column_number,
+                            expected: expected.to_string(),
+                            actual: actual.to_string(),
+                            rewritten_by: column.rewrites_applied(expected, actual),
+                            json_path: match column.column_type {
+                                ColumnType::Json => match json::compare(expected, actual) {
+                                    JsonComparison::Different(path) => Some(path),
+                                    _ => None,
+                                },
+                                _ => None,
+                            },
+                        },
+                        CellComparison::WrongType => problems::LineProblem::TypeMismatch {
+                            line: line_number,
+                            positions: positions.clone(),
+                            column: column_number,
+                            expected: expected.to_string(),
+                            actual: actual.to_string(),
+                            column_type: column.column_type.to_string(),
+                        },
+                        CellComparison::NullAgainstValue => problems::LineProblem::NullMismatch {
+                            line: line_number,
+                            positions: positions.clone(),
+                            column: column_number,
+                            expected: expected.to_string(),
+                            actual: actual.to_string(),
+                        },
+                    };
+                    self.problems.insert_line_problem(section, problem);
                 }
                 EitherOrBoth::Left(_) => {
-                    self.problems
-                        .insert_line_problem(problems::LineProblem::MissingCell {
+                    self.problems.insert_line_problem(
+                        section,
+                        problems::LineProblem::MissingCell {
                             line: line_number,
+                            positions: positions.clone(),
                             column: column_number,
-                        });
+                        },
+                    );
                 }
                 EitherOrBoth::Right(_) => {
-                    self.problems
-                        .insert_line_problem(problems::LineProblem::ExtraCell {
+                    self.problems.insert_line_problem(
+                        section,
+                        problems::LineProblem::ExtraCell {
                             line: line_number,
+                            positions: positions.clone(),
                             column: column_number,
-                        });
+                        },
+                    );
                 }
             }
-
-            column_number += 1;
         }
     }
 
-    fn compare_lines(&mut self, rdr0: &mut csv::Reader<File>, rdr1: &mut csv::Reader<File>) {
-        let mut line_number = 1;
+    /// Pairs up records that have the same key.  Both files are sorted by key first, so they can be merged in one pass
+    /// however large they are.  Keys shared by more than one record of either file are reported, and those records are
+    /// paired up as the options say.
+    fn compare_keyed(
+        &mut self,
+        expected: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        actual: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        columns: &[ColumnComparison],
+        key_columns: &[usize],
+    ) -> Result<(), SourceError> {
+        // Records are matched by key with nulls and values of each column's type written the same way, but the key is
+        // shown as it is written in the file.
+        let nulls = self.options.nulls.clone();
+        let key = |record: &csv::StringRecord| -> Vec<String> {
+            key_columns
+                .iter()
+                .map(|column| {
+                    let cell = record.get(*column).unwrap_or_default();
+                    cells::cell_key(columns, *column, cell, &nulls).into_owned()
+                })
+                .collect()
+        };
+        let shown_key = |record: &csv::StringRecord| {
+            key_columns
+                .iter()
+                .map(|column| record.get(*column).unwrap_or_default())
+                .join(",")
+        };
+        let mut expected =
+            KeyGroups::new(SortedRecords::sort(expected, &key, &self.options.temp_dir)?);
+        let mut actual = KeyGroups::new(SortedRecords::sort(actual, &key, &self.options.temp_dir)?);
 
-        for lines in rdr0.records().zip_longest(rdr1.records()) {
-            match lines {
-                EitherOrBoth::Both(maybe_expected, maybe_actual) => {
-                    match (maybe_expected, maybe_actual) {
-                        (Ok(expected_line), Ok(actual_line)) => {
-                            self.compare_line(line_number, &expected_line, &actual_line)
+        if let Some(patch) = &mut self.patch {
+            patch.key_columns = key_columns.iter().map(|column| column + 1).collect();
+        }
+        let mut row_problems = vec![];
+        let mut found_duplicate_keys = false;
+        let mut next_expected = expected.next().transpose()?;
+        let mut next_actual = actual.next().transpose()?;
+        loop {
+            let ordering = match (&next_expected, &next_actual) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(expected_group), Some(actual_group)) => {
+                    expected_group[0].key.cmp(&actual_group[0].key)
+                }
+            };
+            let (mut expected_group, mut actual_group) = match ordering {
+                Ordering::Less => (next_expected.take().unwrap_or_default(), vec![]),
+                Ordering::Greater => (vec![], next_actual.take().unwrap_or_default()),
+                Ordering::Equal => (
+                    next_expected.take().unwrap_or_default(),
+                    next_actual.take().unwrap_or_default(),
+                ),
+            };
+            if !expected_group.is_empty() {
+                next_expected = expected.next().transpose()?;
+            }
+            if !actual_group.is_empty() {
+                next_actual = actual.next().transpose()?;
+            }
+
+            if expected_group.len() > 1 || actual_group.len() > 1 {
+                found_duplicate_keys = true;
+                let group = expected_group.first().or_else(|| actual_group.first());
+                self.problems.insert_duplicate_key_problem(
+                    group
+                        .map(|keyed| shown_key(&keyed.record))
+                        .unwrap_or_default(),
+                    expected_group.iter().map(|keyed| keyed.line).collect(),
+                    actual_group.iter().map(|keyed| keyed.line).collect(),
+                );
+                if self.options.duplicate_keys == DuplicateKeys::First {
+                    expected_group.truncate(1);
+                    actual_group.truncate(1);
+                }
+            }
+            if found_duplicate_keys && self.options.duplicate_keys == DuplicateKeys::Fail {
+                continue;
+            }
+
+            // Rows are found by key in the patch, unless the key doesn't pick out a single row.
+            let key_is_unique = actual_group.len() == 1;
+            let patch_address = |actual_record: &KeyedRecord| {
+                if key_is_unique {
+                    RowAddress::Key(
+                        key_columns
+                            .iter()
+                            .map(|column| {
+                                actual_record
+                                    .record
+                                    .get(*column)
+                                    .unwrap_or_default()
+                                    .to_string()
+                            })
+                            .collect(),
+                    )
+                } else {
+                    RowAddress::Line(actual_record.line)
+                }
+            };
+            for records in expected_group.into_iter().zip_longest(actual_group) {
+                match records {
+                    EitherOrBoth::Both(expected_record, actual_record) => {
+                        self.compare_line(
+                            Section::Body,
+                            expected_record.line,
+                            &expected_record.record,
+                            &actual_record.record,
+                            columns,
+                            Some(shown_key(&expected_record.record)),
+                        );
+                        self.patch_pair(
+                            patch_address(&actual_record),
+                            &expected_record.record,
+                            &actual_record.record,
+                        );
+                    }
+                    EitherOrBoth::Left(expected_record) => {
+                        if let Some(patch) = &mut self.patch {
+                            patch.insert(None, &expected_record.record);
                         }
-                        (Err(expected_error), Err(actual_error)) => {
-                            self.errors.push(expected_error);
-                            self.errors.push(actual_error);
+                        row_problems.push(RowProblem::MissingRow {
+                            line: expected_record.line,
+                            row: expected_record.record.iter().join(","),
+                            count: 1,
+                        })
+                    }
+                    EitherOrBoth::Right(actual_record) => {
+                        if let Some(patch) = &mut self.patch {
+                            patch.delete(patch_address(&actual_record), &actual_record.record);
                         }
-                        (Err(error), _) => self.errors.push(error),
-                        (_, Err(error)) => self.errors.push(error),
+                        row_problems.push(RowProblem::ExtraRow {
+                            line: actual_record.line,
+                            row: actual_record.record.iter().join(","),
+                            count: 1,
+                        })
+                    }
+                }
+            }
+        }
+        if found_duplicate_keys && self.options.duplicate_keys == DuplicateKeys::Fail {
+            self.problems.discard_body_problems();
+            if let Some(patch) = &mut self.patch {
+                patch.edits.clear();
+            }
+            return Ok(());
+        }
+        self.problems.insert_row_problems(row_problems.into_iter());
+        Ok(())
+    }
+
+    /// Lines up rows by their contents before comparing them, so that an inserted or deleted row only affects itself
+    /// rather than every row after it, and a row with a few changed cells is still compared with its counterpart.
+    /// Both files are read into memory to do this.
+    fn compare_aligned(
+        &mut self,
+        expected: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        actual: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+        columns: &[ColumnComparison],
+    ) -> Result<(), SourceError> {
+        let expected = expected.collect::<Result<Vec<_>, _>>()?;
+        let actual = actual.collect::<Result<Vec<_>, _>>()?;
+        let nulls = &self.options.nulls;
+        let expected_hashes: Vec<u64> = expected
+            .iter()
+            .map(|record| cells::row_hash(record, columns, nulls))
+            .collect();
+        let actual_hashes: Vec<u64> = actual
+            .iter()
+            .map(|record| cells::row_hash(record, columns, nulls))
+            .collect();
+
+        let mut row_problems = vec![];
+        let similarity = |expected_row: usize, actual_row: usize| {
+            cells::row_similarity(&expected[expected_row], &actual[actual_row], columns, nulls)
+        };
+        let alignments = align::align(
+            &expected_hashes,
+            &actual_hashes,
+            similarity,
+            self.options.similarity,
+        );
+
+        // A row missing from the actual file is inserted before the next row of the actual file that stays put.
+        let mut insert_before = vec![actual.len() + 1; alignments.len()];
+        for index in (0..alignments.len().saturating_sub(1)).rev() {
+            insert_before[index] = match alignments[index + 1] {
+                Alignment::Paired(_, actual_row) | Alignment::Extra(actual_row) => actual_row + 1,
+                Alignment::Missing(_) | Alignment::Moved(_, _) => insert_before[index + 1],
+            };
+        }
+
+        for (alignment, before) in alignments.into_iter().zip(insert_before) {
+            match alignment {
+                Alignment::Paired(expected_row, actual_row) => {
+                    self.compare_line(
+                        Section::Body,
+                        expected_row + 1,
+                        &expected[expected_row],
+                        &actual[actual_row],
+                        columns,
+                        None,
+                    );
+                    self.patch_pair(
+                        RowAddress::Line(actual_row + 1),
+                        &expected[expected_row],
+                        &actual[actual_row],
+                    );
+                }
+                Alignment::Missing(expected_row) => {
+                    if let Some(patch) = &mut self.patch {
+                        patch.insert(Some(before), &expected[expected_row]);
+                    }
+                    row_problems.push(RowProblem::MissingRow {
+                        line: expected_row + 1,
+                        row: expected[expected_row].iter().join(","),
+                        count: 1,
+                    })
+                }
+                Alignment::Extra(actual_row) => {
+                    if let Some(patch) = &mut self.patch {
+                        patch.delete(RowAddress::Line(actual_row + 1), &actual[actual_row]);
+                    }
+                    row_problems.push(RowProblem::ExtraRow {
+                        line: actual_row + 1,
+                        row: actual[actual_row].iter().join(","),
+                        count: 1,
+                    })
+                }
+                Alignment::Moved(expected_row, actual_row) => {
+                    if let Some(patch) = &mut self.patch {
+                        patch.delete(RowAddress::Line(actual_row + 1), &actual[actual_row]);
+                        patch.insert(Some(before), &expected[expected_row]);
+                    }
+                    row_problems.push(RowProblem::MovedRow {
+                        from: expected_row + 1,
+                        to: actual_row + 1,
+                        row: actual[actual_row].iter().join(","),
+                    })
+                }
+            }
+        }
+        self.problems.insert_row_problems(row_problems.into_iter());
+        Ok(())
+    }
+
+    fn compare_lines(&mut self, expected: &mut dyn Source, actual: &mut dyn Source) {
+        let column_names = column_names(expected, actual);
+
+        // Types are guessed from the first records of the expected file, which are then compared like the rest.
+        let mut sample = vec![];
+        if self.options.infer_types {
+            for record in (&mut *expected).take(INFERENCE_SAMPLE_SIZE) {
+                match record {
+                    Ok(record) => sample.push(record),
+                    Err(error) => {
+                        self.errors.push(error);
+                        return;
                     }
                 }
-                EitherOrBoth::Left(maybe_expected) => match maybe_expected {
-                    Ok(_) => self.problems.insert_missing_lines_problem(line_number),
-                    Err(error) => self.errors.push(error),
-                },
-                EitherOrBoth::Right(maybe_actual) => match maybe_actual {
-                    Ok(_) => self.problems.insert_extra_lines_problem(line_number),
-                    Err(error) => self.errors.push(error),
-                },
             }
+        }
+        let columns = match column_comparisons(&self.options, expected, actual, &sample) {
+            Ok(columns) => columns,
+            Err(error) => {
+                self.errors.push(error);
+                return;
+            }
+        };
+        self.problems.set_column_names(column_names.clone());
+
+        let expected_lines = sample.into_iter().map(Ok).chain(&mut *expected);
+        if !self.options.key_columns.is_empty() {
+            let key_columns = self
+                .options
+                .key_columns
+                .iter()
+                .map(|selector| {
+                    selector
+                        .resolve(&column_names)
+                        .ok_or_else(|| SourceError::MissingColumn(selector.to_string()))
+                })
+                .collect::<Result<Vec<usize>, SourceError>>()
+                .and_then(|key_columns| {
+                    self.compare_keyed(expected_lines, &mut *actual, &columns, &key_columns)
+                });
+            if let Err(error) = key_columns {
+                self.errors.push(error);
+                return;
+            }
+        } else if self.options.align {
+            if let Err(error) = self.compare_aligned(expected_lines, &mut *actual, &columns) {
+                self.errors.push(error);
+                return;
+            }
+        } else if self.options.unordered {
+            match unordered::compare(
+                expected_lines,
+                &mut *actual,
+                &columns,
+                &self.options.nulls,
+                &self.options.temp_dir,
+            ) {
+                Ok(row_problems) => self.problems.insert_row_problems(row_problems.into_iter()),
+                Err(error) => {
+                    self.errors.push(error);
+                    return;
+                }
+            }
+        } else {
+            for (line_number, lines) in (1..).zip(expected_lines.zip_longest(&mut *actual)) {
+                match lines {
+                    EitherOrBoth::Both(maybe_expected, maybe_actual) => {
+                        match (maybe_expected, maybe_actual) {
+                            (Ok(expected_line), Ok(actual_line)) => {
+                                self.compare_line(
+                                    Section::Body,
+                                    line_number,
+                                    &expected_line,
+                                    &actual_line,
+                                    &columns,
+                                    None,
+                                );
+                                self.patch_pair(
+                                    RowAddress::Line(line_number),
+                                    &expected_line,
+                                    &actual_line,
+                                );
+                            }
+                            (Err(expected_error), Err(actual_error)) => {
+                                self.errors.push(expected_error);
+                                self.errors.push(actual_error);
+                            }
+                            (Err(error), _) => self.errors.push(error),
+                            (_, Err(error)) => self.errors.push(error),
+                        }
+                    }
+                    EitherOrBoth::Left(maybe_expected) => match maybe_expected {
+                        Ok(expected_line) => {
+                            if let Some(patch) = &mut self.patch {
+                                patch.insert(None, &expected_line);
+                            }
+                            self.problems.insert_missing_lines_problem(line_number)
+                        }
+                        Err(error) => self.errors.push(error),
+                    },
+                    EitherOrBoth::Right(maybe_actual) => match maybe_actual {
+                        Ok(actual_line) => {
+                            if let Some(patch) = &mut self.patch {
+                                patch.delete(RowAddress::Line(line_number), &actual_line);
+                            }
+                            self.problems.insert_extra_lines_problem(line_number)
+                        }
+                        Err(error) => self.errors.push(error),
+                    },
+                }
 
-            if !self.errors.is_empty() {
-                break;
+                if !self.errors.is_empty() {
+                    return;
+                }
             }
+        }
+        // Sources without a fixed set of columns, like JSON lines, only know every column once they've been read.
+        self.problems
+            .set_column_names(crate::column_names(expected, actual));
+        if let Some(patch) = &mut self.patch {
+            if !expected.headers().iter().eq(actual.headers().iter()) {
+                patch.header(actual.headers(), expected.headers());
+            }
+        }
 
-            line_number += 1;
+        // Trailers are lined up with each other, however long the rest of the files are.  A trailer line on only one
+        // side is compared against an empty line, so each of its cells shows up as missing or extra.
+        let empty_line = csv::StringRecord::new();
+        for (line_number, lines) in (1..).zip(
+            expected
+                .trailer()
+                .iter()
+                .zip_longest(actual.trailer().iter()),
+        ) {
+            let (expected_line, actual_line) = match lines {
+                EitherOrBoth::Both(expected_line, actual_line) => (expected_line, actual_line),
+                EitherOrBoth::Left(expected_line) => (expected_line, &empty_line),
+                EitherOrBoth::Right(actual_line) => (&empty_line, actual_line),
+            };
+            self.compare_line(
+                Section::Trailer,
+                line_number,
+                expected_line,
+                actual_line,
+                &columns,
+                None,
+            );
         }
     }
 }
 
-fn handle_crash<T: Debug>(errors: &Vec<T>) {
+fn handle_crash<T: Display>(errors: &[T]) {
     let mut log_filepath = env::temp_dir();
     log_filepath.push(format!("richdiff_crash_{:?}.log", SystemTime::now()));
     let mut log_file = File::create(log_filepath.clone()).unwrap();
@@ -174,7 +812,7 @@ fn handle_crash<T: Debug>(errors: &Vec<T>) {
         .write_all(
             errors
                 .iter()
-                .map(|error| format!("{:?}", error))
+                .map(|error| format!("{}", error))
                 .join("\n")
                 .as_ref(),
         )
@@ -188,36 +826,698 @@ fn handle_crash<T: Debug>(errors: &Vec<T>) {
     exit(1);
 }
 
-fn get_reader<P: AsRef<Path>>(filepath: P, delimiter: Delimiter) -> csv::Result<csv::Reader<File>> {
-    let delimiter_byte = match delimiter {
-        Delimiter::Comma => b',',
-        Delimiter::Pipe => b'|',
-        Delimiter::Tab => b'\t',
+fn handle_failed_source(error: SourceError, file: &str) -> Result<(), SourceError> {
+    match &error {
+        SourceError::MissingSheet(sheet) => {
+            eprintln!(
+                "{} has no sheet named {} - did you mistype the sheet name?",
+                file, sheet
+            );
+            return Ok(());
+        }
+        SourceError::MissingLayout => {
+            eprintln!("{} is fixed-width, so it needs a layout to be read.", file);
+            return Ok(());
+        }
+        SourceError::Layout(message) => {
+            eprintln!("The layout for {} could not be read - {}", file, message);
+            return Ok(());
+        }
+        SourceError::UnsupportedSkip(format) => {
+            eprintln!(
+                "{} is a {} file, which doesn't have lines that can be skipped that way.",
+                file, format
+            );
+            return Ok(());
+        }
+        SourceError::MissingQuery => {
+            eprintln!(
+                "{} is a SQLite database, so it needs a table or query to be read.",
+                file
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+    match error.io_error().map(|io_error| io_error.kind()) {
+        Some(io::ErrorKind::NotFound) => {
+            eprintln!("{} does not exist - did you mistype the file name?", file);
+            Ok(())
+        }
+        Some(io::ErrorKind::PermissionDenied) => {
+            eprintln!("{} cannot be read due to its permissions.", file);
+            Ok(())
+        }
+        _ => Err(error),
+    }
+}
+
+fn handle_failed_comparison(error: ComparisonError) {
+    match error {
+        ComparisonError::Open(errors) => {
+            let unhandled_errors: Vec<SourceError> = errors
+                .into_iter()
+                .filter_map(|(filepath, error)| handle_failed_source(error, &filepath).err())
+                .collect();
+            if !unhandled_errors.is_empty() {
+                handle_crash(&unhandled_errors);
+            }
+        }
+        ComparisonError::Read(errors) => {
+            let unhandled_errors: Vec<SourceError> = errors
+                .into_iter()
+                .filter(|error| match error {
+                    SourceError::MissingColumn(column) => {
+                        eprintln!("Neither file has a column named {}.", column);
+                        false
+                    }
+                    _ => true,
+                })
+                .collect();
+            if !unhandled_errors.is_empty() {
+                handle_crash(&unhandled_errors);
+            }
+        }
+    }
+}
+
+/// The outcome of comparing two files.
+struct Comparison {
+    problems: Problems,
+    patch: Option<Patch>,
+    expected_sheet: Option<String>,
+    actual_sheet: Option<String>,
+}
+
+/// One report covering the comparison of an expected file with each of several actual files.
+#[derive(Serialize)]
+struct CombinedReport {
+    expected_filename: String,
+    num_problems: usize,
+    sections: Vec<CombinedSection>,
+}
+
+#[derive(Serialize)]
+struct CombinedSection {
+    actual_filename: String,
+    problems: Option<DisplayProblems>,
+    error: Option<String>,
+}
+
+enum ComparisonError {
+    /// One or both of the files couldn't be opened, listed with the path of each.
+    Open(Vec<(String, SourceError)>),
+    /// Something went wrong partway through reading the files.
+    Read(Vec<SourceError>),
+}
+
+impl Display for ComparisonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Open(errors) => write!(
+                f,
+                "{}",
+                errors
+                    .iter()
+                    .map(|(filepath, error)| format!("{}: {}", filepath, error))
+                    .join("; ")
+            ),
+            Self::Read(errors) => write!(f, "{}", errors.iter().join("; ")),
+        }
+    }
+}
+
+fn compare_files(
+    options: &CompareOptions,
+    expected_filepath: &Path,
+    expected_options: &SourceOptions,
+    actual_filepath: &Path,
+    actual_options: &SourceOptions,
+) -> Result<Comparison, ComparisonError> {
+    let (mut expected, mut actual) = match (
+        get_source(expected_filepath, expected_options),
+        get_source(actual_filepath, actual_options),
+    ) {
+        (Ok(expected), Ok(actual)) => (expected, actual),
+        (expected, actual) => {
+            return Err(ComparisonError::Open(
+                expected
+                    .err()
+                    .map(|error| (expected_filepath.display().to_string(), error))
+                    .into_iter()
+                    .chain(
+                        actual
+                            .err()
+                            .map(|error| (actual_filepath.display().to_string(), error)),
+                    )
+                    .collect(),
+            ))
+        }
+    };
+    compare_sources(
+        options,
+        expected.as_mut(),
+        expected_options.format,
+        actual.as_mut(),
+        actual_options.format,
+    )
+}
+
+/// Names each column from the header of the expected file, or of the actual file where the expected file has no name
+/// for it.
+fn column_names(expected: &dyn Source, actual: &dyn Source) -> Vec<String> {
+    (0..max(expected.headers().len(), actual.headers().len()))
+        .map(|column| {
+            expected
+                .headers()
+                .get(column)
+                .filter(|name| !name.is_empty())
+                .or_else(|| actual.headers().get(column))
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Works out how each column is compared.  When only one side knows the type of a column, the values on both sides
+/// are read as that type.  Types can also be guessed from a sample of the expected file's records.
+fn column_comparisons(
+    options: &CompareOptions,
+    expected: &dyn Source,
+    actual: &dyn Source,
+    sample: &[csv::StringRecord],
+) -> Result<Vec<ColumnComparison>, SourceError> {
+    let num_columns = max(expected.headers().len(), actual.headers().len());
+    let column_names = column_names(expected, actual);
+    let resolve = |selector: &ColumnSelector| {
+        selector
+            .resolve(&column_names)
+            .ok_or_else(|| SourceError::MissingColumn(selector.to_string()))
     };
-    csv::ReaderBuilder::new()
-        // With the expected file as the source of truth, we can't assume that it has a consistent number of rows.
-        // The flexible option ensures that doesn't surface as an error.
+    for (selector, _) in &options.normalizations.columns {
+        resolve(selector)?;
+    }
+    for rewrite in &options.rewrites {
+        resolve(&rewrite.column)?;
+    }
+    let normalizations = options.normalizations.for_columns(&column_names);
+    let mut rewrites = rewrite::for_columns(&options.rewrites, &column_names);
+    let compared_columns = options
+        .columns
+        .iter()
+        .map(|range| {
+            range
+                .resolve(&column_names)
+                .ok_or_else(|| SourceError::MissingColumn(range.to_string()))
+        })
+        .collect::<Result<Vec<Vec<usize>>, SourceError>>()?;
+    let compared_columns: Option<Vec<usize>> = if options.columns.is_empty() {
+        None
+    } else {
+        Some(compared_columns.concat())
+    };
+    let json_columns = options
+        .json_columns
+        .iter()
+        .map(resolve)
+        .collect::<Result<Vec<usize>, SourceError>>()?;
+    let inferred_types: Vec<Option<ColumnType>> = (0..num_columns)
+        .map(|column| {
+            if sample.is_empty() {
+                return None;
+            }
+            ColumnType::infer(
+                sample
+                    .iter()
+                    .filter_map(|record| record.get(column))
+                    .filter(|value| !options.nulls.tokens.iter().any(|token| token == value)),
+            )
+        })
+        .collect();
+
+    Ok((0..num_columns)
+        .map(|column| ColumnComparison {
+            column_type: if json_columns.contains(&column) {
+                ColumnType::Json
+            } else {
+                expected
+                    .column_type(column)
+                    .or_else(|| actual.column_type(column))
+                    .or(inferred_types[column])
+                    .unwrap_or(ColumnType::Text)
+            },
+            checks_type: !json_columns.contains(&column)
+                && expected.column_type(column).is_none()
+                && actual.column_type(column).is_none()
+                && inferred_types[column].is_some(),
+            rewrites: std::mem::take(&mut rewrites[column]),
+            normalization: normalizations[column],
+            compared: compared_columns
+                .as_ref()
+                .is_none_or(|compared_columns| compared_columns.contains(&column)),
+        })
+        .collect())
+}
+
+fn compare_sources(
+    options: &CompareOptions,
+    expected: &mut dyn Source,
+    expected_format: Format,
+    actual: &mut dyn Source,
+    actual_format: Format,
+) -> Result<Comparison, ComparisonError> {
+    // A source whose columns are matched by name takes its column order from the other file, preferring the expected
+    // file when both are matched by name.  Then columns first seen partway through either file line up too.
+    if !expected_format.matches_columns_by_name() || actual_format.matches_columns_by_name() {
+        actual.align_to(&expected.headers().clone());
+        if let Some(columns) = expected.named_columns() {
+            actual.share_named_columns(columns);
+        }
+    } else {
+        expected.align_to(&actual.headers().clone());
+    }
+
+    let mut summary = Summary::new(None, options);
+    match &options.filter {
+        None => summary.compare_lines(expected, actual),
+        Some(filter) => {
+            let column_names = column_names(expected, actual);
+            let condition = filter
+                .resolve(&column_names)
+                .map_err(|error| ComparisonError::Read(vec![error]))?;
+            let mut expected = FilteredSource::new(expected, condition.clone());
+            let mut actual = FilteredSource::new(actual, condition);
+            summary.compare_lines(&mut expected, &mut actual);
+            summary.problems.set_filter_summary(FilterSummary {
+                expression: filter.to_string(),
+                expected_excluded: expected.excluded,
+                actual_excluded: actual.excluded,
+            });
+        }
+    }
+    if !summary.errors.is_empty() {
+        return Err(ComparisonError::Read(summary.errors));
+    }
+
+    Ok(Comparison {
+        problems: summary.problems,
+        patch: summary.patch,
+        expected_sheet: expected.sheet_name().map(|sheet| sheet.to_string()),
+        actual_sheet: actual.sheet_name().map(|sheet| sheet.to_string()),
+    })
+}
+
+/// Applies a patch to a file, writing the result to a temporary file first so the original is left alone if the patch
+/// doesn't apply.
+fn apply_patch(matches: &ArgMatches) -> Result<(), patch::PatchError> {
+    let patch = patch::read(matches.value_of("PATCH").unwrap())?;
+    let filepath = Path::new(matches.value_of("FILE").unwrap());
+    let output_filepath = matches
+        .value_of("output")
+        .map(Path::new)
+        .unwrap_or(filepath);
+    let delimiter = value_t!(matches, "delimiter", Delimiter).unwrap_or(Delimiter::Comma);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter.as_byte())
         .flexible(true)
-        .delimiter(delimiter_byte)
-        .from_path(filepath)
+        .from_path(filepath)?;
+    let output_dir = match output_filepath.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter.as_byte())
+        .flexible(true)
+        .from_writer(tempfile::NamedTempFile::new_in(output_dir)?);
+    patch::apply(&patch, &mut reader, &mut writer)?;
+    let output = writer
+        .into_inner()
+        .map_err(|error| patch::PatchError::IO(error.into_error()))?;
+    output
+        .persist(output_filepath)
+        .map_err(|error| patch::PatchError::IO(error.error))?;
+    Ok(())
 }
 
-fn handle_failed_reader(error: csv::Error, file: &str) -> Result<(), csv::Error> {
-    match error.kind() {
-        csv::ErrorKind::Io(io_error) => match io_error.kind() {
-            io::ErrorKind::NotFound => Ok(eprintln!(
-                "{} does not exist - did you mistype the file name?",
-                file
-            )),
-            io::ErrorKind::PermissionDenied => {
-                Ok(eprintln!("{} cannot be read due to its permissions.", file))
+/// Compares the expected and actual files as two files made from a common base file, with the expected file as ours
+/// and the actual file as theirs.  Columns are lined up with ours, and compared the way ours and theirs would be.
+fn compare_three_way(
+    matches: &ArgMatches,
+    options: &CompareOptions,
+    filepaths: [&Path; 3],
+) -> Result<ThreeWayComparison, ComparisonError> {
+    let [base_filepath, ours_filepath, theirs_filepath] = filepaths;
+    let open = |filepath: &Path, side: &str| {
+        get_source(filepath, &source_options(matches, side, filepath))
+            .map_err(|error| (filepath.display().to_string(), error))
+    };
+    let (mut base, mut ours, mut theirs) = match (
+        open(base_filepath, "expected"),
+        open(ours_filepath, "expected"),
+        open(theirs_filepath, "actual"),
+    ) {
+        (Ok(base), Ok(ours), Ok(theirs)) => (base, ours, theirs),
+        (base, ours, theirs) => {
+            return Err(ComparisonError::Open(
+                vec![base.err(), ours.err(), theirs.err()]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            ))
+        }
+    };
+    base.align_to(&ours.headers().clone());
+    theirs.align_to(&ours.headers().clone());
+    if let Some(columns) = ours.named_columns() {
+        base.share_named_columns(columns.clone());
+        theirs.share_named_columns(columns);
+    }
+
+    let columns = column_comparisons(options, ours.as_ref(), theirs.as_ref(), &[])
+        .map_err(|error| ComparisonError::Read(vec![error]))?;
+    let column_names = column_names(ours.as_ref(), theirs.as_ref());
+    let key_columns = options
+        .key_columns
+        .iter()
+        .map(|selector| {
+            selector
+                .resolve(&column_names)
+                .ok_or_else(|| SourceError::MissingColumn(selector.to_string()))
+        })
+        .collect::<Result<Vec<usize>, SourceError>>()
+        .map_err(|error| ComparisonError::Read(vec![error]))?;
+    let mut comparison = three_way::compare(
+        [base.as_mut(), ours.as_mut(), theirs.as_mut()],
+        &columns,
+        &column_names,
+        &key_columns,
+        &options.nulls,
+        DEFAULT_MAX_PROBLEMS,
+    )
+    .map_err(|error| ComparisonError::Read(vec![error]))?;
+    let [base_filename, ours_filename, theirs_filename] =
+        filepaths.map(|filepath| filepath.display().to_string());
+    comparison.base_filename = base_filename;
+    comparison.ours_filename = ours_filename;
+    comparison.theirs_filename = theirs_filename;
+    Ok(comparison)
+}
+
+/// Compares one expected file with several actual files, reading the expected file only once.
+fn compare_many(
+    matches: &ArgMatches,
+    options: &CompareOptions,
+    expected_filepath: &Path,
+    actual_filepaths: &[PathBuf],
+) -> Result<CombinedReport, ComparisonError> {
+    let expected_options = source_options(matches, "expected", expected_filepath);
+    let recorded = get_source(expected_filepath, &expected_options)
+        .map_err(|error| {
+            ComparisonError::Open(vec![(expected_filepath.display().to_string(), error)])
+        })
+        .and_then(|mut expected| {
+            RecordedSource::record(expected.as_mut())
+                .map_err(|error| ComparisonError::Read(vec![error]))
+        })?;
+
+    let mut report = CombinedReport {
+        expected_filename: expected_filepath.display().to_string(),
+        num_problems: 0,
+        sections: vec![],
+    };
+    for actual_filepath in actual_filepaths {
+        let actual_filename = actual_filepath.display().to_string();
+        let actual_options = source_options(matches, "actual", actual_filepath);
+        let comparison = get_source(actual_filepath, &actual_options)
+            .map_err(|error| ComparisonError::Open(vec![(actual_filename.clone(), error)]))
+            .and_then(|mut actual| {
+                compare_sources(
+                    options,
+                    &mut recorded.replay(),
+                    expected_options.format,
+                    actual.as_mut(),
+                    actual_options.format,
+                )
+            });
+        report.sections.push(match comparison {
+            Ok(comparison) => {
+                if matches.is_present("print") {
+                    print_problems(&actual_filename, &comparison.problems);
+                }
+                report.num_problems += comparison.problems.len();
+                CombinedSection {
+                    problems: Some(comparison.problems.display_data(
+                        &actual_filename,
+                        comparison.expected_sheet.as_deref(),
+                        comparison.actual_sheet.as_deref(),
+                    )),
+                    actual_filename,
+                    error: None,
+                }
+            }
+            Err(error) => CombinedSection {
+                actual_filename,
+                problems: None,
+                error: Some(error.to_string()),
+            },
+        });
+    }
+    Ok(report)
+}
+
+/// Lists the files matching each actual path given on the command line, for shells that don't expand wildcards.
+fn expand_actual_filepaths<'a>(values: impl Iterator<Item = &'a str>) -> Vec<PathBuf> {
+    values
+        .flat_map(|value| {
+            let filepath = PathBuf::from(value);
+            if filepath.exists() {
+                return vec![filepath];
+            }
+            // A path that matches nothing is kept, so that it is reported as missing.
+            match glob::glob(value).map(|paths| paths.filter_map(Result::ok).collect::<Vec<_>>()) {
+                Ok(filepaths) if !filepaths.is_empty() => filepaths,
+                _ => vec![filepath],
+            }
+        })
+        .collect()
+}
+
+/// Compares every file in the expected directory with the file at the same place in the actual directory, writing a
+/// report for each pair under `out/`.
+fn compare_directories(
+    matches: &ArgMatches,
+    options: &CompareOptions,
+    expected_directory: &Path,
+    actual_directory: &Path,
+) -> Result<Index, ReportError> {
+    let expected_files = directories::files_in(expected_directory)?;
+    let actual_files = directories::files_in(actual_directory)?;
+    let mut index = Index::new(expected_directory, actual_directory);
+
+    for relative_path in expected_files.union(&actual_files) {
+        match (
+            expected_files.contains(relative_path),
+            actual_files.contains(relative_path),
+        ) {
+            (true, true) => {}
+            (true, false) => {
+                index.insert_only_in_expected(relative_path);
+                continue;
+            }
+            (false, _) => {
+                index.insert_only_in_actual(relative_path);
+                continue;
+            }
+        }
+
+        let expected_filepath = expected_directory.join(relative_path);
+        let actual_filepath = actual_directory.join(relative_path);
+        let expected_options = source_options(matches, "expected", &expected_filepath);
+        let actual_options = source_options(matches, "actual", &actual_filepath);
+        match compare_files(
+            options,
+            &expected_filepath,
+            &expected_options,
+            &actual_filepath,
+            &actual_options,
+        ) {
+            Ok(comparison) => {
+                if matches.is_present("print") {
+                    print_problems(&relative_path.display().to_string(), &comparison.problems);
+                }
+                let report_filepath =
+                    PathBuf::from("out").join(format!("{}.html", relative_path.display()));
+                if let Some(parent) = report_filepath.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                generate_report(&comparison, &actual_filepath, &report_filepath)?;
+                index.insert_compared(relative_path, &report_filepath, comparison.problems.len());
             }
-            _ => Err(error),
+            Err(error) => index.insert_failed(relative_path, &error.to_string()),
+        }
+    }
+
+    Ok(index)
+}
+
+/// Reads the options for one side of the comparison, given as `expected` or `actual`.
+fn source_options(matches: &ArgMatches, side: &str, filepath: &Path) -> SourceOptions {
+    let layout = matches
+        .value_of(format!("{}-layout", side))
+        .map(PathBuf::from);
+    let query = match (
+        matches.value_of(format!("{}-table", side)),
+        matches.value_of(format!("{}-query", side)),
+    ) {
+        (Some(table), _) => Some(SqliteQuery::Table(table.to_string())),
+        (None, Some(sql)) => Some(SqliteQuery::Sql(sql.to_string())),
+        (None, None) => None,
+    };
+    // Layouts only make sense for fixed-width files, and tables and queries for SQLite databases, so giving one is
+    // enough to pick the format.
+    let default_format = if layout.is_some() {
+        Format::FixedWidth
+    } else if query.is_some() {
+        Format::Sqlite
+    } else {
+        Format::from_path(filepath)
+    };
+    SourceOptions {
+        format: value_t!(matches, &format!("{}-format", side), Format).unwrap_or(default_format),
+        delimiter: value_t!(matches, &format!("{}-delimiter", side), Delimiter)
+            .unwrap_or(Delimiter::Comma),
+        sheet: matches
+            .value_of(format!("{}-sheet", side))
+            .map(|sheet| sheet.to_string()),
+        layout,
+        query,
+        skip: SkipOptions {
+            leading_lines: value_t!(matches, &format!("{}-skip-lines", side), usize).unwrap_or(0),
+            prefix: matches
+                .value_of(format!("{}-skip-prefix", side))
+                .map(|prefix| prefix.to_string()),
+            // The pattern was already checked when the arguments were parsed.
+            pattern: matches
+                .value_of(format!("{}-skip-pattern", side))
+                .map(|pattern| Regex::new(pattern).unwrap()),
         },
-        _ => Err(error),
+        trailer_lines: value_t!(matches, &format!("{}-trailer-lines", side), usize).unwrap_or(0),
+    }
+}
+
+fn compare_options(matches: &ArgMatches) -> CompareOptions {
+    // The normalizations were already checked when the arguments were parsed.
+    CompareOptions {
+        normalizations: Normalizations {
+            all_columns: matches
+                .value_of("normalize")
+                .map(|rules| Normalization::parse(rules).unwrap())
+                .unwrap_or_default(),
+            columns: matches
+                .values_of("normalize-column")
+                .map(|values| {
+                    values
+                        .map(|value| Normalizations::parse_column(value).unwrap())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        },
+        nulls: Nulls {
+            tokens: match matches.values_of("null-token") {
+                Some(tokens) => tokens.map(|token| token.to_string()).collect(),
+                None if matches.is_present("nulls") || matches.is_present("report-nulls") => {
+                    Nulls::DEFAULT_TOKENS
+                        .iter()
+                        .map(|token| token.to_string())
+                        .collect()
+                }
+                None => vec![],
+            },
+            report_separately: matches.is_present("report-nulls"),
+        },
+        rewrites: matches
+            .values_of("rewrite")
+            .map(|values| {
+                let values: Vec<&str> = values.collect();
+                (1..)
+                    .zip(values.chunks(3))
+                    .map(|(number, rule)| {
+                        Rewrite::new(number, rule[0], rule[1], rule[2]).unwrap_or_else(|error| {
+                            clap::Error::with_description(
+                                &format!("{} is not a valid pattern - {}", rule[1], error),
+                                clap::ErrorKind::ValueValidation,
+                            )
+                            .exit()
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        json_columns: matches
+            .values_of("json-column")
+            .map(|columns| columns.map(ColumnSelector::parse).collect())
+            .unwrap_or_default(),
+        columns: matches
+            .value_of("columns")
+            .map(|columns| ColumnRange::parse_list(columns).unwrap_or_default())
+            .unwrap_or_default(),
+        filter: matches
+            .value_of("where")
+            .and_then(|expression| Filter::parse(expression).ok()),
+        write_patch: matches.is_present("write-patch"),
+        infer_types: matches.is_present("infer-types"),
+        unordered: matches.is_present("unordered"),
+        align: matches.is_present("align"),
+        similarity: value_t!(matches, "similarity", f64).unwrap_or(DEFAULT_SIMILARITY),
+        key_columns: matches
+            .values_of("key")
+            .map(|columns| columns.map(ColumnSelector::parse).collect())
+            .unwrap_or_default(),
+        duplicate_keys: value_t!(matches, "duplicate-keys", DuplicateKeys).unwrap_or_default(),
+        temp_dir: matches
+            .value_of("temp-dir")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir),
     }
 }
 
+fn is_number(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("{} is not a whole number", value))
+}
+
+fn is_column_list(value: String) -> Result<(), String> {
+    ColumnRange::parse_list(&value).map(|_| ())
+}
+
+fn is_filter(value: String) -> Result<(), String> {
+    Filter::parse(&value).map(|_| ())
+}
+
+fn is_fraction(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(()),
+        _ => Err(format!("{} is not a number between 0 and 1", value)),
+    }
+}
+
+fn is_pattern(value: String) -> Result<(), String> {
+    Regex::new(&value)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+fn is_normalization(value: String) -> Result<(), String> {
+    Normalization::parse(&value).map(|_| ())
+}
+
+fn is_column_normalization(value: String) -> Result<(), String> {
+    Normalizations::parse_column(&value).map(|_| ())
+}
+
 fn main() {
     let matches = App::new("richdiff")
         .version("1.0")
@@ -243,72 +1543,497 @@ fn main() {
                 .possible_values(&Delimiter::variants())
                 .case_insensitive(true),
         )
+        .arg(
+            Arg::with_name("expected-format")
+                .long("expected-format")
+                .value_name("FORMAT")
+                .help("Indicates the format of the expected file.  Guessed from the file extension if not given.")
+                .takes_value(true)
+                .possible_values(&Format::variants())
+                .case_insensitive(true),
+        )
+        .arg(
+            Arg::with_name("actual-format")
+                .long("actual-format")
+                .value_name("FORMAT")
+                .help("Indicates the format of the actual file.  Guessed from the file extension if not given.")
+                .takes_value(true)
+                .possible_values(&Format::variants())
+                .case_insensitive(true),
+        )
+        .arg(
+            Arg::with_name("expected-sheet")
+                .long("expected-sheet")
+                .value_name("SHEET")
+                .help("The name or position (starting from 1) of the sheet to read, when the expected file is a workbook.  Defaults to the first sheet.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("actual-sheet")
+                .long("actual-sheet")
+                .value_name("SHEET")
+                .help("The name or position (starting from 1) of the sheet to read, when the actual file is a workbook.  Defaults to the first sheet.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expected-layout")
+                .long("expected-layout")
+                .value_name("LAYOUT")
+                .help("The path to a CSV file laying out the fields of the expected file, when it is fixed-width.  Each field has a name, start, length and optionally a trim (both, left, right or none).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("actual-layout")
+                .long("actual-layout")
+                .value_name("LAYOUT")
+                .help("The path to a CSV file laying out the fields of the actual file, when it is fixed-width.  Each field has a name, start, length and optionally a trim (both, left, right or none).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expected-table")
+                .long("expected-table")
+                .value_name("TABLE")
+                .help("The table to read, when the expected file is a SQLite database.")
+                .takes_value(true)
+                .conflicts_with("expected-query"),
+        )
+        .arg(
+            Arg::with_name("expected-query")
+                .long("expected-query")
+                .value_name("SQL")
+                .help("The query to read the results of, when the expected file is a SQLite database.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("actual-table")
+                .long("actual-table")
+                .value_name("TABLE")
+                .help("The table to read, when the actual file is a SQLite database.")
+                .takes_value(true)
+                .conflicts_with("actual-query"),
+        )
+        .arg(
+            Arg::with_name("actual-query")
+                .long("actual-query")
+                .value_name("SQL")
+                .help("The query to read the results of, when the actual file is a SQLite database.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expected-skip-lines")
+                .long("expected-skip-lines")
+                .value_name("N")
+                .help("Skips this many lines at the start of the expected file, before its header.")
+                .takes_value(true)
+                .validator(is_number),
+        )
+        .arg(
+            Arg::with_name("actual-skip-lines")
+                .long("actual-skip-lines")
+                .value_name("N")
+                .help("Skips this many lines at the start of the actual file, before its header.")
+                .takes_value(true)
+                .validator(is_number),
+        )
+        .arg(
+            Arg::with_name("expected-skip-prefix")
+                .long("expected-skip-prefix")
+                .value_name("PREFIX")
+                .help("Skips lines in the expected file that start with this, such as # for comments.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("actual-skip-prefix")
+                .long("actual-skip-prefix")
+                .value_name("PREFIX")
+                .help("Skips lines in the actual file that start with this, such as # for comments.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expected-skip-pattern")
+                .long("expected-skip-pattern")
+                .value_name("REGEX")
+                .help("Skips lines in the expected file that match this regular expression.")
+                .takes_value(true)
+                .validator(is_pattern),
+        )
+        .arg(
+            Arg::with_name("actual-skip-pattern")
+                .long("actual-skip-pattern")
+                .value_name("REGEX")
+                .help("Skips lines in the actual file that match this regular expression.")
+                .takes_value(true)
+                .validator(is_pattern),
+        )
+        .arg(
+            Arg::with_name("expected-trailer-lines")
+                .long("expected-trailer-lines")
+                .value_name("N")
+                .help("Treats this many lines at the end of the expected file as a trailer, compared separately from the rest of the file.")
+                .takes_value(true)
+                .validator(is_number),
+        )
+        .arg(
+            Arg::with_name("actual-trailer-lines")
+                .long("actual-trailer-lines")
+                .value_name("N")
+                .help("Treats this many lines at the end of the actual file as a trailer, compared separately from the rest of the file.")
+                .takes_value(true)
+                .validator(is_number),
+        )
+        .arg(
+            Arg::with_name("normalize")
+                .long("normalize")
+                .value_name("RULES")
+                .help("Normalizes every cell before comparing it, while still reporting the cells as they were.  RULES is a comma-separated list of trim, collapse (runs of whitespace become one space), case, nfc and nfkc.")
+                .takes_value(true)
+                .validator(is_normalization),
+        )
+        .arg(
+            Arg::with_name("normalize-column")
+                .long("normalize-column")
+                .value_name("COLUMN=RULES")
+                .help("Normalizes the cells of one column, given by name or number, in place of --normalize.  RULES are the same as for --normalize, or none to compare the column as it is.  Can be given more than once.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_column_normalization),
+        )
+        .arg(
+            Arg::with_name("rewrite")
+                .long("rewrite")
+                .value_names(&["COLUMN", "PATTERN", "REPLACEMENT"])
+                .help("Replaces whatever matches the regular expression PATTERN in a column, given by name or number, before comparing its cells.  The replacement can refer to groups in the pattern, like $1.  Can be given more than once, and rules for the same column are applied in order.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(3),
+        )
+        .arg(
+            Arg::with_name("json-column")
+                .long("json-column")
+                .value_name("COLUMN")
+                .help("Compares the cells of a column, given by name or number, as JSON, so the order of keys and the way numbers are written don't matter.  Can be given more than once.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .help("Compares only these columns, given as a list of names, numbers and ranges of numbers separated by commas, like name,4,7-10.  Problems are still reported at their column's number in the files.")
+                .takes_value(true)
+                .validator(is_column_list),
+        )
+        .arg(
+            Arg::with_name("where")
+                .long("where")
+                .value_name("FILTER")
+                .help("Compares only the rows of both files that match a filter, like 'region = North and not (total < 100 or code ~ \"^X\")'.  Cells are compared as numbers or dates when they can be, and ~ matches a regular expression.")
+                .takes_value(true)
+                .validator(is_filter),
+        )
+        .arg(
+            Arg::with_name("write-baseline")
+                .long("write-baseline")
+                .value_name("FILE")
+                .help("Writes every problem found to a baseline file, so they can be approved and left out of later comparisons with --baseline.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .long("baseline")
+                .value_name("FILE")
+                .help("Leaves out the problems approved in a baseline file written by --write-baseline, reporting only new problems along with approved ones that no longer happen.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("write-patch")
+                .long("write-patch")
+                .value_name("FILE")
+                .help("Writes a patch of the header changes, row insertions, deletions and cell updates that turn the actual file into the expected one, which the apply subcommand can replay.  Rows are found by key when --key is given, and by record number otherwise.  The actual file has to be a delimited file read from its first line to its last.")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "unordered",
+                    "where",
+                    "actual-skip-lines",
+                    "actual-skip-prefix",
+                    "actual-skip-pattern",
+                    "actual-trailer-lines",
+                ]),
+        )
+        .arg(
+            Arg::with_name("base")
+                .long("base")
+                .value_name("FILE")
+                .help("Compares the expected and actual files as two sets of changes made to this common base file, reporting which cells changed in only one of them, which changed the same way in both and which conflict.  Rows are lined up by position, or by key when --key is given.")
+                .takes_value(true)
+                .conflicts_with_all(&["unordered", "align", "where", "write-patch", "baseline", "write-baseline"]),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .value_name("COLUMN")
+                .help("Pairs up rows by the value of this column, given by name or number, rather than by position.  Give it more than once for a key made of several columns.  Files too large for memory are sorted by key on disk.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("unordered"),
+        )
+        .arg(
+            Arg::with_name("duplicate-keys")
+                .long("duplicate-keys")
+                .value_name("PAIRING")
+                .help("How rows that share a key are paired up: only the first row with the key in each file, in the order they appear in each file, or not at all, comparing no rows if any key is shared. [default: positional]")
+                .takes_value(true)
+                .possible_values(&DuplicateKeys::variants())
+                .case_insensitive(true)
+                .requires("key"),
+        )
+        .arg(
+            Arg::with_name("temp-dir")
+                .long("temp-dir")
+                .value_name("DIR")
+                .help("Where to put temporary files when a file is too large to compare in memory.  Defaults to the system's temporary directory.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("align")
+                .long("align")
+                .help("Lines up rows by their contents, like a text diff, so that inserted, deleted and moved rows are reported as such rather than throwing off every row after them.")
+                .conflicts_with_all(&["key", "unordered"]),
+        )
+        .arg(
+            Arg::with_name("similarity")
+                .long("similarity")
+                .value_name("FRACTION")
+                .help("When lining up rows, pairs a changed row with a row that replaced it if at least this fraction of their cells are the same. [default: 0.5]")
+                .requires("align")
+                .validator(is_fraction),
+        )
+        .arg(
+            Arg::with_name("unordered")
+                .long("unordered")
+                .help("Compares the files as collections of rows in any order, reporting rows only in one file and rows that appear a different number of times in each.")
+        )
+        .arg(
+            Arg::with_name("infer-types")
+                .long("infer-types")
+                .help("Guesses whether each column holds integers, decimals, booleans, dates or text from the expected file, comparing cells as that type and reporting actual cells of another type as type mismatches.")
+        )
+        .arg(
+            Arg::with_name("nulls")
+                .long("nulls")
+                .help("Treats empty cells, NULL, \\N, NA and null as the same missing value.")
+        )
+        .arg(
+            Arg::with_name("null-token")
+                .long("null-token")
+                .value_name("TOKEN")
+                .help("Treats this as a missing value, in place of the values --nulls uses.  Can be given more than once.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("report-nulls")
+                .long("report-nulls")
+                .help("Reports a missing value against a value as a null mismatch, rather than a mismatched cell.  Implies --nulls.")
+        )
+        .arg(
+            Arg::with_name("print")
+                .long("print")
+                .help("Prints the problems to the terminal as well as writing the report, showing what changed within mismatched cells.")
+        )
         .arg(
             Arg::with_name("EXPECTED")
-                .help("The path to the file that is the source of truth.")
+                .help("The path to the file that is the source of truth, or a directory of such files.")
                 .required(true)
                 .index(1),
         )
         .arg(
             Arg::with_name("ACTUAL")
-                .help("The path to the file that needs to look like the source of truth.")
+                .help("The path to the file that needs to look like the source of truth, or a directory of files matched to the expected ones by path.  Several files, or a wildcard pattern, compare each of them against the expected file in one report.")
                 .required(true)
+                .multiple(true)
                 .index(2),
         )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Applies a patch written by --write-patch to the actual file, turning it into the expected one.")
+                .arg(
+                    Arg::with_name("PATCH")
+                        .help("The patch to apply.")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("The delimited file to apply the patch to.  It is changed in place unless --output is given.")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .help("Where to write the patched file, rather than over the original.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("delimiter")
+                        .short("d")
+                        .long("delimiter")
+                        .value_name("DELIMITER")
+                        .help("Indicates the delimiter of the file.")
+                        .takes_value(true)
+                        .possible_values(&Delimiter::variants())
+                        .case_insensitive(true),
+                ),
+        )
+        .setting(AppSettings::SubcommandsNegateReqs)
         .get_matches();
 
-    let expected_filepath = matches.value_of("EXPECTED").unwrap();
-    let actual_filepath = matches.value_of("ACTUAL").unwrap();
-    let expected_delimiter =
-        value_t!(matches, "expected-delimiter", Delimiter).unwrap_or(Delimiter::Comma);
-    let actual_delimiter =
-        value_t!(matches, "actual-delimiter", Delimiter).unwrap_or(Delimiter::Comma);
+    if let Some(apply_matches) = matches.subcommand_matches("apply") {
+        if let Err(error) = apply_patch(apply_matches) {
+            eprintln!("Could not apply the patch: {}", error);
+            exit(1);
+        }
+        return;
+    }
 
-    match (
-        get_reader(expected_filepath, expected_delimiter),
-        get_reader(actual_filepath, actual_delimiter),
-    ) {
-        (Ok(ref mut rdr0), Ok(ref mut rdr1)) => {
-            let mut summary = Summary::new(None);
-            summary.compare_lines(rdr0, rdr1);
-            if !summary.errors.is_empty() {
-                handle_crash(&summary.errors);
-            }
+    let expected_filepath = Path::new(matches.value_of("EXPECTED").unwrap());
+    let options = compare_options(&matches);
+    let actual_filepaths = expand_actual_filepaths(matches.values_of("ACTUAL").unwrap());
 
-            if let Err(report_error) =
-                generate_report(&summary.problems, actual_filepath, "out.html")
-            {
-                handle_crash(&vec![report_error]);
-            }
+    let uses_baseline = matches.is_present("baseline") || matches.is_present("write-baseline");
+    if uses_baseline && (actual_filepaths.len() > 1 || expected_filepath.is_dir()) {
+        eprintln!("Baseline files can only be used when comparing one file with another.");
+        return;
+    }
+    if options.write_patch && (actual_filepaths.len() > 1 || expected_filepath.is_dir()) {
+        eprintln!("A patch can only be written when comparing one file with another.");
+        return;
+    }
+    // The apply subcommand reads the actual file back as a delimited file, so record numbers have to mean the same
+    // thing to it.
+    if options.write_patch
+        && source_options(&matches, "actual", &actual_filepaths[0]).format != Format::Csv
+    {
+        eprintln!("A patch can only be written when the actual file is a delimited file.");
+        return;
+    }
+
+    if let Some(base_filepath) = matches.value_of("base") {
+        if actual_filepaths.len() > 1 || expected_filepath.is_dir() {
+            eprintln!("A three-way comparison can only be made between single files.");
+            return;
         }
-        (Err(e0), Err(e1)) => {
-            let mut errors = vec![];
-            if let Err(error) = handle_failed_reader(e0, expected_filepath) {
-                errors.push(error);
-            }
-            if let Err(error) = handle_failed_reader(e1, actual_filepath) {
-                errors.push(error);
-            }
-            if !errors.is_empty() {
-                handle_crash(&errors);
+        let filepaths = [
+            Path::new(base_filepath),
+            expected_filepath,
+            actual_filepaths[0].as_path(),
+        ];
+        match compare_three_way(&matches, &options, filepaths) {
+            Ok(comparison) => {
+                if matches.is_present("print") {
+                    print_three_way(&comparison);
+                }
+                if let Err(report_error) = generate_three_way_report(&comparison, "out.html") {
+                    handle_crash(&[report_error]);
+                }
             }
+            Err(error) => handle_failed_comparison(error),
         }
-        (Err(e), _) => {
-            let mut errors = vec![];
-            if let Err(error) = handle_failed_reader(e, expected_filepath) {
-                errors.push(error);
-            }
-            if !errors.is_empty() {
-                handle_crash(&errors);
+        return;
+    }
+
+    if actual_filepaths.len() > 1 {
+        if expected_filepath.is_dir() {
+            eprintln!("A directory can only be compared with one other directory.");
+            return;
+        }
+        match compare_many(&matches, &options, expected_filepath, &actual_filepaths) {
+            Ok(report) => {
+                if let Err(report_error) = generate_combined_report(&report, "out.html") {
+                    handle_crash(&[report_error]);
+                }
             }
+            Err(error) => handle_failed_comparison(error),
         }
-        (_, Err(e)) => {
-            let mut errors = vec![];
-            if let Err(error) = handle_failed_reader(e, actual_filepath) {
-                errors.push(error);
+        return;
+    }
+
+    let actual_filepath = actual_filepaths[0].as_path();
+    match (expected_filepath.is_dir(), actual_filepath.is_dir()) {
+        (true, true) => {
+            match compare_directories(&matches, &options, expected_filepath, actual_filepath) {
+                Ok(index) => {
+                    if let Err(report_error) = generate_index(&index, "out.html") {
+                        handle_crash(&[report_error]);
+                    }
+                }
+                Err(report_error) => handle_crash(&[report_error]),
             }
-            if !errors.is_empty() {
-                handle_crash(&errors);
+        }
+        (true, false) | (false, true) => {
+            eprintln!("To compare directories, both the expected and actual paths need to be directories.");
+        }
+        (false, false) => {
+            let expected_options = source_options(&matches, "expected", expected_filepath);
+            let actual_options = source_options(&matches, "actual", actual_filepath);
+            match compare_files(
+                &options,
+                expected_filepath,
+                &expected_options,
+                actual_filepath,
+                &actual_options,
+            ) {
+                Ok(mut comparison) => {
+                    if let (Some(patch_filepath), Some(patch)) =
+                        (matches.value_of("write-patch"), &comparison.patch)
+                    {
+                        if let Err(error) = patch::write(patch_filepath, patch) {
+                            eprintln!("Could not write the patch {}: {}", patch_filepath, error);
+                            return;
+                        }
+                    }
+                    if let Some(baseline_filepath) = matches.value_of("write-baseline") {
+                        let entries = comparison.problems.baseline_entries();
+                        if let Err(error) = baseline::write(baseline_filepath, &entries) {
+                            eprintln!(
+                                "Could not write the baseline file {}: {}",
+                                baseline_filepath, error
+                            );
+                            return;
+                        }
+                    }
+                    if let Some(baseline_filepath) = matches.value_of("baseline") {
+                        match baseline::read(baseline_filepath) {
+                            Ok(entries) => comparison.problems.subtract_baseline(entries),
+                            Err(error) => {
+                                eprintln!(
+                                    "Could not read the baseline file {}: {}",
+                                    baseline_filepath, error
+                                );
+                                return;
+                            }
+                        }
+                    }
+                    if matches.is_present("print") {
+                        print_problems(
+                            &actual_filepath.display().to_string(),
+                            &comparison.problems,
+                        );
+                    }
+                    if let Err(report_error) =
+                        generate_report(&comparison, actual_filepath, "out.html")
+                    {
+                        handle_crash(&[report_error]);
+                    }
+                }
+                Err(error) => handle_failed_comparison(error),
             }
         }
     }
@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::columns::ColumnSelector;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnicodeForm {
+    Nfc,
+    Nfkc,
+}
+
+/// Ways of changing a cell before it is compared, so that differences nobody cares about aren't reported.  Reports
+/// still show the cells as they were in the files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Normalization {
+    pub trim: bool,
+    pub collapse_whitespace: bool,
+    pub case_fold: bool,
+    pub unicode: Option<UnicodeForm>,
+}
+
+impl Normalization {
+    /// Parses a comma-separated list of rules, such as `trim,case`.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut normalization = Normalization::default();
+        for rule in rules.split(',').map(str::trim) {
+            match rule.to_lowercase().as_str() {
+                "trim" => normalization.trim = true,
+                "collapse" => normalization.collapse_whitespace = true,
+                "case" => normalization.case_fold = true,
+                "nfc" => normalization.unicode = Some(UnicodeForm::Nfc),
+                "nfkc" => normalization.unicode = Some(UnicodeForm::Nfkc),
+                "none" => {}
+                _ => {
+                    return Err(format!(
+                        "{} is not a normalization - use trim, collapse, case, nfc, nfkc or none",
+                        rule
+                    ))
+                }
+            }
+        }
+        Ok(normalization)
+    }
+
+    pub fn apply<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value = Cow::Borrowed(value);
+        if let Some(form) = self.unicode {
+            value = Cow::Owned(match form {
+                UnicodeForm::Nfc => value.nfc().collect(),
+                UnicodeForm::Nfkc => value.nfkc().collect(),
+            });
+        }
+        if self.trim {
+            value = match value {
+                Cow::Borrowed(value) => Cow::Borrowed(value.trim()),
+                Cow::Owned(value) => Cow::Owned(value.trim().to_string()),
+            };
+        }
+        if self.collapse_whitespace {
+            value = Cow::Owned(collapse_whitespace(&value));
+        }
+        if self.case_fold {
+            value = Cow::Owned(value.to_lowercase());
+        }
+        value
+    }
+}
+
+/// Replaces each run of whitespace with a single space, keeping whatever is at the ends.
+fn collapse_whitespace(value: &str) -> String {
+    let mut collapsed = String::with_capacity(value.len());
+    let mut in_whitespace = false;
+    for character in value.chars() {
+        if character.is_whitespace() {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(character);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+/// The normalization for every cell, with any given for particular columns used in its place for those columns.
+#[derive(Debug, Clone, Default)]
+pub struct Normalizations {
+    pub all_columns: Normalization,
+    pub columns: Vec<(ColumnSelector, Normalization)>,
+}
+
+impl Normalizations {
+    /// Parses a column's normalization, given as `COLUMN=RULES`.
+    pub fn parse_column(value: &str) -> Result<(ColumnSelector, Normalization), String> {
+        match value.rsplit_once('=') {
+            Some((column, rules)) => {
+                Ok((ColumnSelector::parse(column), Normalization::parse(rules)?))
+            }
+            None => Err(format!("{} should look like COLUMN=RULES", value)),
+        }
+    }
+
+    /// Works out the normalization for each column, given the names of the columns.
+    pub fn for_columns(&self, column_names: &[String]) -> Vec<Normalization> {
+        let mut normalizations = vec![self.all_columns; column_names.len()];
+        for (selector, normalization) in &self.columns {
+            if let Some(column) = selector.resolve(column_names) {
+                normalizations[column] = *normalization;
+            }
+        }
+        normalizations
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Where a row of the actual file is: its record number, or the cells of its key columns when rows were paired up by
+/// key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowAddress {
+    Line(usize),
+    Key(Vec<String>),
+}
+
+impl Display for RowAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Line(line) => write!(f, "record {}", line),
+            Self::Key(key) => write!(f, "key {}", key.iter().join(",")),
+        }
+    }
+}
+
+/// One change to the actual file.  Updates and deletions give the cells they expect to find, so a patch can't be
+/// applied to a file it wasn't made for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "edit", rename_all = "lowercase")]
+pub enum Edit {
+    Update {
+        row: RowAddress,
+        column: usize,
+        from: String,
+        to: String,
+    },
+    Delete {
+        row: RowAddress,
+        cells: Vec<String>,
+    },
+    /// A row inserted before the given record of the actual file, or at the end if there's no such record.
+    Insert {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<usize>,
+        cells: Vec<String>,
+    },
+    /// The header of the actual file, replaced as a whole.
+    Header {
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+}
+
+/// The edits that turn the actual file into the expected one.  Rows are found by record number, or by the cells of
+/// the key columns, given by number, when rows were paired up by key.  Rows paired up by key keep their order, with
+/// new rows added at the end.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Patch {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_columns: Vec<usize>,
+    pub edits: Vec<Edit>,
+}
+
+impl Patch {
+    pub fn update(&mut self, row: RowAddress, column: usize, from: &str, to: &str) {
+        self.edits.push(Edit::Update {
+            row,
+            column,
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+
+    pub fn delete(&mut self, row: RowAddress, cells: &csv::StringRecord) {
+        self.edits.push(Edit::Delete {
+            row,
+            cells: cells.iter().map(String::from).collect(),
+        });
+    }
+
+    pub fn insert(&mut self, before: Option<usize>, cells: &csv::StringRecord) {
+        self.edits.push(Edit::Insert {
+            before,
+            cells: cells.iter().map(String::from).collect(),
+        });
+    }
+
+    pub fn header(&mut self, from: &csv::StringRecord, to: &csv::StringRecord) {
+        self.edits.push(Edit::Header {
+            from: from.iter().map(String::from).collect(),
+            to: to.iter().map(String::from).collect(),
+        });
+    }
+
+    fn key_address(&self, record: &csv::StringRecord) -> Option<RowAddress> {
+        if self.key_columns.is_empty() {
+            return None;
+        }
+        Some(RowAddress::Key(
+            self.key_columns
+                .iter()
+                .map(|column| record.get(column - 1).unwrap_or_default().to_string())
+                .collect(),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    IO(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// The file doesn't hold what the patch expects, so it wasn't made for this file.
+    DoesNotApply(String),
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IO(error) => write!(f, "{}", error),
+            Self::Csv(error) => write!(f, "{}", error),
+            Self::Json(error) => write!(f, "{}", error),
+            Self::DoesNotApply(reason) => write!(f, "the patch does not apply, since {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for PatchError {
+    fn from(error: io::Error) -> Self {
+        PatchError::IO(error)
+    }
+}
+
+impl From<csv::Error> for PatchError {
+    fn from(error: csv::Error) -> Self {
+        PatchError::Csv(error)
+    }
+}
+
+impl From<serde_json::Error> for PatchError {
+    fn from(error: serde_json::Error) -> Self {
+        PatchError::Json(error)
+    }
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Patch, PatchError> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn write<P: AsRef<Path>>(path: P, patch: &Patch) -> Result<(), PatchError> {
+    let writer = BufWriter::new(File::create(path)?);
+    Ok(serde_json::to_writer_pretty(writer, patch)?)
+}
+
+/// Applies a patch to a delimited file with a header, writing the patched file.  The header is copied as it is, unless
+/// the patch replaces it.
+pub fn apply<R: Read, W: Write>(
+    patch: &Patch,
+    reader: &mut csv::Reader<R>,
+    writer: &mut csv::Writer<W>,
+) -> Result<(), PatchError> {
+    let mut updates: HashMap<&RowAddress, Vec<(usize, &str, &str)>> = HashMap::new();
+    let mut deletes: HashMap<&RowAddress, &[String]> = HashMap::new();
+    let mut inserts: BTreeMap<usize, Vec<&[String]>> = BTreeMap::new();
+    let mut inserts_at_end = vec![];
+    let mut header = None;
+    for edit in &patch.edits {
+        match edit {
+            Edit::Update {
+                row,
+                column,
+                from,
+                to,
+            } => updates
+                .entry(row)
+                .or_default()
+                .push((*column, from.as_str(), to.as_str())),
+            Edit::Delete { row, cells } => {
+                deletes.insert(row, cells);
+            }
+            Edit::Insert {
+                before: Some(before),
+                cells,
+            } => inserts.entry(*before).or_default().push(cells),
+            Edit::Insert {
+                before: None,
+                cells,
+            } => inserts_at_end.push(cells.as_slice()),
+            Edit::Header { from, to } => header = Some((from, to)),
+        }
+    }
+
+    let headers = reader.headers()?;
+    match header {
+        Some((from, _)) if !headers.iter().eq(from.iter()) => {
+            return Err(PatchError::DoesNotApply(format!(
+                "the header is {}, not {}",
+                headers.iter().join(","),
+                from.iter().join(",")
+            )))
+        }
+        Some((_, to)) => writer.write_record(to)?,
+        None => writer.write_record(headers)?,
+    }
+    for (line, record) in (1..).zip(reader.records()) {
+        let mut record = record?;
+        for cells in inserts.remove(&line).unwrap_or_default() {
+            writer.write_record(cells)?;
+        }
+
+        let address = patch
+            .key_address(&record)
+            .filter(|address| deletes.contains_key(address) || updates.contains_key(address))
+            .unwrap_or(RowAddress::Line(line));
+        if let Some(cells) = deletes.remove(&address) {
+            if !record.iter().eq(cells.iter()) {
+                return Err(PatchError::DoesNotApply(format!(
+                    "the row to delete at {} is {}, not {}",
+                    address,
+                    record.iter().join(","),
+                    cells.iter().join(",")
+                )));
+            }
+            continue;
+        }
+        if let Some(cell_updates) = updates.remove(&address) {
+            let mut cells: Vec<String> = record.iter().map(String::from).collect();
+            for (column, from, to) in cell_updates {
+                match cells.get_mut(column - 1) {
+                    Some(cell) if cell == from => *cell = to.to_string(),
+                    cell => {
+                        return Err(PatchError::DoesNotApply(format!(
+                            "column {} at {} is {}, not {}",
+                            column,
+                            address,
+                            cell.map(|cell| cell.as_str()).unwrap_or("missing"),
+                            from
+                        )))
+                    }
+                }
+            }
+            record = csv::StringRecord::from(cells);
+        }
+        writer.write_record(&record)?;
+    }
+    for cells in inserts.into_values().flatten().chain(inserts_at_end) {
+        writer.write_record(cells)?;
+    }
+
+    let unused = updates.keys().chain(deletes.keys()).next();
+    match unused {
+        Some(row) => Err(PatchError::DoesNotApply(format!(
+            "there is no row at {}",
+            row
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "id,name,qty\n1,apple,5\n2,pear,3\n3,plum,7\n";
+
+    fn record(cells: &str) -> csv::StringRecord {
+        cells.split(',').collect()
+    }
+
+    fn apply_to(patch: &Patch, file: &str) -> Result<String, PatchError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(file.as_bytes());
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+        apply(patch, &mut reader, &mut writer)?;
+        Ok(String::from_utf8(writer.into_inner().unwrap()).unwrap())
+    }
+
+    fn reason(result: Result<String, PatchError>) -> String {
+        match result {
+            Err(PatchError::DoesNotApply(reason)) => reason,
+            other => panic!("expected the patch not to apply, but got {:?}", other),
+        }
+    }
+
+    /// Writes the patch out and reads it back, so the tests cover the patch file too.
+    fn round_trip(patch: &Patch) -> Patch {
+        serde_json::from_str(&serde_json::to_string(patch).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn applies_edits_by_line() {
+        let mut patch = Patch::default();
+        patch.update(RowAddress::Line(1), 3, "5", "6");
+        patch.delete(RowAddress::Line(2), &record("2,pear,3"));
+        patch.insert(Some(3), &record("4,fig,1"));
+        patch.insert(None, &record("5,kiwi,2"));
+        assert_eq!(
+            apply_to(&round_trip(&patch), FILE).unwrap(),
+            "id,name,qty\n1,apple,6\n4,fig,1\n3,plum,7\n5,kiwi,2\n"
+        );
+    }
+
+    #[test]
+    fn applies_edits_by_key() {
+        let mut patch = Patch {
+            key_columns: vec![1],
+            edits: vec![],
+        };
+        patch.update(RowAddress::Key(vec!["3".to_string()]), 2, "plum", "Plum");
+        patch.delete(RowAddress::Key(vec!["1".to_string()]), &record("1,apple,5"));
+        patch.insert(None, &record("4,fig,1"));
+        assert_eq!(
+            apply_to(
+                &round_trip(&patch),
+                "id,name,qty\n3,plum,7\n2,pear,3\n1,apple,5\n"
+            )
+            .unwrap(),
+            "id,name,qty\n3,Plum,7\n2,pear,3\n4,fig,1\n"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_lines_for_rows_without_key_edits() {
+        let mut patch = Patch {
+            key_columns: vec![1],
+            edits: vec![],
+        };
+        patch.update(RowAddress::Line(2), 3, "3", "4");
+        patch.update(RowAddress::Line(3), 3, "7", "8");
+        assert_eq!(
+            apply_to(&patch, "id,name,qty\n1,apple,5\n1,pear,3\n1,plum,7\n").unwrap(),
+            "id,name,qty\n1,apple,5\n1,pear,4\n1,plum,8\n"
+        );
+    }
+
+    #[test]
+    fn empty_patch_copies_the_file() {
+        assert_eq!(apply_to(&Patch::default(), FILE).unwrap(), FILE);
+    }
+
+    #[test]
+    fn rejects_updates_to_other_values() {
+        let mut patch = Patch::default();
+        patch.update(RowAddress::Line(2), 2, "plum", "Plum");
+        assert_eq!(
+            reason(apply_to(&patch, FILE)),
+            "column 2 at record 2 is pear, not plum"
+        );
+    }
+
+    #[test]
+    fn rejects_updates_to_missing_cells() {
+        let mut patch = Patch::default();
+        patch.update(RowAddress::Line(1), 4, "x", "y");
+        assert_eq!(
+            reason(apply_to(&patch, FILE)),
+            "column 4 at record 1 is missing, not x"
+        );
+    }
+
+    #[test]
+    fn rejects_deletions_of_other_rows() {
+        let mut patch = Patch::default();
+        patch.delete(RowAddress::Line(3), &record("3,plum,8"));
+        assert_eq!(
+            reason(apply_to(&patch, FILE)),
+            "the row to delete at record 3 is 3,plum,7, not 3,plum,8"
+        );
+    }
+
+    #[test]
+    fn rejects_edits_to_rows_that_are_not_there() {
+        let mut patch = Patch::default();
+        patch.update(RowAddress::Line(9), 1, "9", "10");
+        assert_eq!(
+            reason(apply_to(&patch, FILE)),
+            "there is no row at record 9"
+        );
+
+        let mut patch = Patch {
+            key_columns: vec![1],
+            edits: vec![],
+        };
+        patch.delete(RowAddress::Key(vec!["7".to_string()]), &record("7,lime,1"));
+        assert_eq!(reason(apply_to(&patch, FILE)), "there is no row at key 7");
+    }
+
+    #[test]
+    fn replaces_the_header() {
+        let mut patch = Patch::default();
+        patch.header(&record("id,name,qty"), &record("id,NAME,qty"));
+        patch.update(RowAddress::Line(1), 3, "5", "5.0");
+        assert_eq!(
+            apply_to(&round_trip(&patch), FILE).unwrap(),
+            "id,NAME,qty\n1,apple,5.0\n2,pear,3\n3,plum,7\n"
+        );
+
+        let mut patch = Patch::default();
+        patch.header(&record("id,title,qty"), &record("id,NAME,qty"));
+        assert_eq!(
+            reason(apply_to(&patch, FILE)),
+            "the header is id,name,qty, not id,title,qty"
+        );
+    }
+}
@@ -1,17 +1,27 @@
 use std::cmp::min;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use itertools::Itertools;
 use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 
+use crate::baseline::BaselineEntry;
+use crate::cell_diff::{self, Segment};
+
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum ProblemCategory {
     MismatchedCells,
+    NullMismatches,
+    TypeMismatches,
     ExtraCells,
     MissingCells,
     ExtraLines,
     MissingLines,
+    ExtraRows,
+    MissingRows,
+    DuplicateRows,
+    MovedRows,
+    DuplicateKeys,
 }
 
 impl Serialize for ProblemCategory {
@@ -29,6 +39,22 @@ impl Serialize for ProblemCategory {
                     "The contents of one or more cells in the actual file did not match up.",
                 )?;
             }
+            Self::NullMismatches => {
+                category.serialize_entry("type", "Null mismatches")?;
+                category.serialize_entry("color", "purple")?;
+                category.serialize_entry(
+                    "description",
+                    "One or more cells were missing a value in one file but had a value in the other.",
+                )?;
+            }
+            Self::TypeMismatches => {
+                category.serialize_entry("type", "Type mismatches")?;
+                category.serialize_entry("color", "brown")?;
+                category.serialize_entry(
+                    "description",
+                    "One or more cells in the actual file held a different type of value than the rest of their column.",
+                )?;
+            }
             Self::ExtraCells => {
                 category.serialize_entry("type", "Extra cells")?;
                 category.serialize_entry("color", "orange")?;
@@ -61,29 +87,290 @@ impl Serialize for ProblemCategory {
                     "The actual file had fewer lines in it than expected.",
                 )?;
             }
+            Self::ExtraRows => {
+                category.serialize_entry("type", "Extra rows")?;
+                category.serialize_entry("color", "green")?;
+                category.serialize_entry(
+                    "description",
+                    "The actual file had rows that are nowhere in the expected file.",
+                )?;
+            }
+            Self::MissingRows => {
+                category.serialize_entry("type", "Missing rows")?;
+                category.serialize_entry("color", "blue")?;
+                category.serialize_entry(
+                    "description",
+                    "The actual file was missing rows that are in the expected file.",
+                )?;
+            }
+            Self::DuplicateRows => {
+                category.serialize_entry("type", "Duplicate rows")?;
+                category.serialize_entry("color", "teal")?;
+                category.serialize_entry(
+                    "description",
+                    "A row (or rows) appeared a different number of times in each file.",
+                )?;
+            }
+            Self::MovedRows => {
+                category.serialize_entry("type", "Moved rows")?;
+                category.serialize_entry("color", "darkcyan")?;
+                category.serialize_entry(
+                    "description",
+                    "A row (or rows) in the actual file was unchanged, but in a different place than expected.",
+                )?;
+            }
+            Self::DuplicateKeys => {
+                category.serialize_entry("type", "Duplicate keys")?;
+                category.serialize_entry("color", "maroon")?;
+                category.serialize_entry(
+                    "description",
+                    "More than one row had the same key, so it wasn't clear which rows to compare.",
+                )?;
+            }
         };
         category.end()
     }
 }
 
+/// Where a line starts in each file, for files that have lines.  These can differ from the line number the comparison
+/// gives, since that counts records, and a record can span more than one line.  When rows are paired up by key, the
+/// key of the row is kept too.
+#[derive(Debug, Clone, Default)]
+pub struct Positions {
+    pub expected: Option<csv::Position>,
+    pub actual: Option<csv::Position>,
+    pub key: Option<String>,
+}
+
+impl Positions {
+    fn lines_description(&self) -> Option<String> {
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) if expected.line() == actual.line() => {
+                Some(format!("line {}", actual.line()))
+            }
+            (Some(expected), Some(actual)) => Some(format!(
+                "line {} of the actual file, line {} of the expected file",
+                actual.line(),
+                expected.line()
+            )),
+            (Some(expected), None) => {
+                Some(format!("line {} of the expected file", expected.line()))
+            }
+            (None, Some(actual)) => Some(format!("line {} of the actual file", actual.line())),
+            (None, None) => None,
+        }
+    }
+
+    fn bytes_description(&self) -> String {
+        let mut bytes = vec![];
+        if let Some(actual) = &self.actual {
+            bytes.push(format!("byte {} of the actual file", actual.byte()));
+        }
+        if let Some(expected) = &self.expected {
+            bytes.push(format!("byte {} of the expected file", expected.byte()));
+        }
+        if bytes.is_empty() {
+            String::new()
+        } else {
+            format!("Starts at {}.", bytes.join(" and "))
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum LineProblem {
     MismatchedCell {
         line: usize,
+        positions: Positions,
         column: usize,
         expected: String,
         actual: String,
+        /// The rewrite rules that changed either value before they were compared.
+        rewritten_by: Option<String>,
+        /// Where the values first differ, when they were compared as JSON.
+        json_path: Option<String>,
+    },
+    /// A cell that was null in one file but had a value in the other.
+    NullMismatch {
+        line: usize,
+        positions: Positions,
+        column: usize,
+        expected: String,
+        actual: String,
+    },
+    /// A cell in the actual file that couldn't be read as the type of its column.
+    TypeMismatch {
+        line: usize,
+        positions: Positions,
+        column: usize,
+        expected: String,
+        actual: String,
+        column_type: String,
     },
     ExtraCell {
         line: usize,
+        positions: Positions,
         column: usize,
     },
     MissingCell {
         line: usize,
+        positions: Positions,
         column: usize,
     },
 }
 
+impl LineProblem {
+    fn positions(&self) -> &Positions {
+        match self {
+            Self::MismatchedCell {
+                line: _,
+                positions,
+                column: _,
+                expected: _,
+                actual: _,
+                rewritten_by: _,
+                json_path: _,
+            } => positions,
+            Self::NullMismatch {
+                line: _,
+                positions,
+                column: _,
+                expected: _,
+                actual: _,
+            } => positions,
+            Self::TypeMismatch {
+                line: _,
+                positions,
+                column: _,
+                expected: _,
+                actual: _,
+                column_type: _,
+            } => positions,
+            Self::ExtraCell {
+                line: _,
+                positions,
+                column: _,
+            } => positions,
+            Self::MissingCell {
+                line: _,
+                positions,
+                column: _,
+            } => positions,
+        }
+    }
+
+    /// Remembers the problem for a baseline file, by the key of its row if it has one and its record number if not.
+    fn baseline_entry(&self, section: Section) -> BaselineEntry {
+        let (mut entry, line, positions) = match self {
+            Self::MismatchedCell {
+                line,
+                positions,
+                column: _,
+                expected,
+                actual,
+                rewritten_by: _,
+                json_path: _,
+            } => (
+                BaselineEntry {
+                    expected: Some(expected.clone()),
+                    actual: Some(actual.clone()),
+                    ..BaselineEntry::new("Mismatched cell")
+                },
+                line,
+                positions,
+            ),
+            Self::NullMismatch {
+                line,
+                positions,
+                column: _,
+                expected,
+                actual,
+            } => (
+                BaselineEntry {
+                    expected: Some(expected.clone()),
+                    actual: Some(actual.clone()),
+                    ..BaselineEntry::new("Null mismatch")
+                },
+                line,
+                positions,
+            ),
+            Self::TypeMismatch {
+                line,
+                positions,
+                column: _,
+                expected,
+                actual,
+                column_type: _,
+            } => (
+                BaselineEntry {
+                    expected: Some(expected.clone()),
+                    actual: Some(actual.clone()),
+                    ..BaselineEntry::new("Type mismatch")
+                },
+                line,
+                positions,
+            ),
+            Self::ExtraCell {
+                line,
+                positions,
+                column: _,
+            } => (BaselineEntry::new("Extra cell"), line, positions),
+            Self::MissingCell {
+                line,
+                positions,
+                column: _,
+            } => (BaselineEntry::new("Missing cell"), line, positions),
+        };
+        match &positions.key {
+            Some(key) => entry.key = Some(key.clone()),
+            None => entry.line = Some(*line),
+        }
+        entry.trailer = section == Section::Trailer;
+        entry.column = Some(self.column());
+        entry
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            Self::MismatchedCell {
+                line: _,
+                positions: _,
+                column,
+                expected: _,
+                actual: _,
+                rewritten_by: _,
+                json_path: _,
+            } => *column,
+            Self::NullMismatch {
+                line: _,
+                positions: _,
+                column,
+                expected: _,
+                actual: _,
+            } => *column,
+            Self::TypeMismatch {
+                line: _,
+                positions: _,
+                column,
+                expected: _,
+                actual: _,
+                column_type: _,
+            } => *column,
+            Self::ExtraCell {
+                line: _,
+                positions: _,
+                column,
+            } => *column,
+            Self::MissingCell {
+                line: _,
+                positions: _,
+                column,
+            } => *column,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ExtraLinesProblem {
     line: usize,
@@ -96,35 +383,523 @@ pub struct MissingLinesProblem {
     num_missing: usize,
 }
 
+/// A key shared by more than one row of either file, along with the records that have it in each file.
+#[derive(Debug, Clone)]
+pub struct DuplicateKeyProblem {
+    key: String,
+    expected_lines: Vec<usize>,
+    actual_lines: Vec<usize>,
+}
+
+impl ExtraLinesProblem {
+    fn baseline_entry(&self) -> BaselineEntry {
+        BaselineEntry {
+            line: Some(self.line),
+            ..BaselineEntry::new("Extra line")
+        }
+    }
+}
+
+impl MissingLinesProblem {
+    fn baseline_entry(&self) -> BaselineEntry {
+        BaselineEntry {
+            line: Some(self.line),
+            ..BaselineEntry::new("Missing line")
+        }
+    }
+}
+
+impl DuplicateKeyProblem {
+    fn baseline_entry(&self) -> BaselineEntry {
+        BaselineEntry {
+            key: Some(self.key.clone()),
+            ..BaselineEntry::new("Duplicate key")
+        }
+    }
+}
+
+/// A problem with a whole row, found when the order of the rows doesn't matter.  Each row is shown along with the
+/// record it first appears at in each file.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone)]
+pub enum RowProblem {
+    ExtraRow {
+        line: usize,
+        row: String,
+        count: usize,
+    },
+    MissingRow {
+        line: usize,
+        row: String,
+        count: usize,
+    },
+    DuplicateRow {
+        expected_line: usize,
+        actual_line: usize,
+        row: String,
+        expected_count: usize,
+        actual_count: usize,
+    },
+    /// A row that is the same in both files, but in a different place.
+    MovedRow { from: usize, to: usize, row: String },
+}
+
+impl RowProblem {
+    /// Remembers the problem for a baseline file, by the row itself rather than where it was.
+    fn baseline_entry(&self) -> BaselineEntry {
+        match self {
+            Self::ExtraRow {
+                line: _,
+                row,
+                count: _,
+            } => BaselineEntry {
+                row: Some(row.clone()),
+                ..BaselineEntry::new("Extra row")
+            },
+            Self::MissingRow {
+                line: _,
+                row,
+                count: _,
+            } => BaselineEntry {
+                row: Some(row.clone()),
+                ..BaselineEntry::new("Missing row")
+            },
+            Self::DuplicateRow {
+                expected_line: _,
+                actual_line: _,
+                row,
+                expected_count,
+                actual_count,
+            } => BaselineEntry {
+                row: Some(row.clone()),
+                expected: Some(expected_count.to_string()),
+                actual: Some(actual_count.to_string()),
+                ..BaselineEntry::new("Duplicate row")
+            },
+            Self::MovedRow {
+                from: _,
+                to: _,
+                row,
+            } => BaselineEntry {
+                row: Some(row.clone()),
+                ..BaselineEntry::new("Moved row")
+            },
+        }
+    }
+
+    /// The record the row first appears at, in the actual file if it's there.
+    fn line(&self) -> usize {
+        match self {
+            Self::ExtraRow {
+                line,
+                row: _,
+                count: _,
+            } => *line,
+            Self::MissingRow {
+                line,
+                row: _,
+                count: _,
+            } => *line,
+            Self::DuplicateRow {
+                expected_line: _,
+                actual_line,
+                row: _,
+                expected_count: _,
+                actual_count: _,
+            } => *actual_line,
+            Self::MovedRow {
+                from: _,
+                to,
+                row: _,
+            } => *to,
+        }
+    }
+}
+
+/// Describes how many times a row appears, when it's more than once.
+fn times_label(count: usize) -> String {
+    if count > 1 {
+        format!("  It appears {} times.", count)
+    } else {
+        String::new()
+    }
+}
+
 #[derive(Debug)]
 pub enum FileProblem {
     ExtraLines(ExtraLinesProblem),
     MissingLines(MissingLinesProblem),
+    DuplicateKey(DuplicateKeyProblem),
+}
+
+/// The part of the file a line is in.  Trailer lines are numbered from the start of the trailer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Body,
+    Trailer,
 }
 
 #[derive(Debug)]
 pub enum Problem {
-    Line(LineProblem),
+    Line {
+        problem: LineProblem,
+        column_name: Option<String>,
+        section: Section,
+    },
+    Row(RowProblem),
     File(FileProblem),
 }
 
+/// Describes a line by the number of the record it holds, along with the line it starts on in the files if that's
+/// known.
+fn line_label(line: usize, section: Section, positions: &Positions) -> String {
+    let record = match section {
+        Section::Body => format!("record {}", line),
+        Section::Trailer => format!("trailer record {}", line),
+    };
+    match positions.lines_description() {
+        Some(lines) => format!("{} ({})", record, lines),
+        None => record,
+    }
+}
+
+/// Describes a column by its number, along with its name from the header if it has one.
+fn column_label(column: usize, column_name: &Option<String>) -> String {
+    match column_name {
+        Some(name) if !name.is_empty() => format!("column {} ({})", column, name),
+        _ => format!("column {}", column),
+    }
+}
+
+/// Lists the records of one file, or nothing if there aren't any.
+fn records_label(lines: &[usize], file: &str) -> Option<String> {
+    match lines {
+        [] => None,
+        [line] => Some(format!("record {} of the {} file", line, file)),
+        _ => Some(format!(
+            "records {} of the {} file",
+            lines.iter().join(", "),
+            file
+        )),
+    }
+}
+
+/// Shows a value so that an empty one can still be seen.
+fn display_value(value: &str) -> &str {
+    if value.is_empty() {
+        "empty"
+    } else {
+        value
+    }
+}
+
 impl Problem {
     pub fn category(&self) -> ProblemCategory {
         match self {
-            Self::Line(LineProblem::MismatchedCell {
+            Self::Line {
+                problem:
+                    LineProblem::MismatchedCell {
+                        line: _,
+                        positions: _,
+                        column: _,
+                        expected: _,
+                        actual: _,
+                        rewritten_by: _,
+                        json_path: _,
+                    },
+                column_name: _,
+                section: _,
+            } => ProblemCategory::MismatchedCells,
+            Self::Line {
+                problem:
+                    LineProblem::NullMismatch {
+                        line: _,
+                        positions: _,
+                        column: _,
+                        expected: _,
+                        actual: _,
+                    },
+                column_name: _,
+                section: _,
+            } => ProblemCategory::NullMismatches,
+            Self::Line {
+                problem:
+                    LineProblem::TypeMismatch {
+                        line: _,
+                        positions: _,
+                        column: _,
+                        expected: _,
+                        actual: _,
+                        column_type: _,
+                    },
+                column_name: _,
+                section: _,
+            } => ProblemCategory::TypeMismatches,
+            Self::Line {
+                problem:
+                    LineProblem::ExtraCell {
+                        line: _,
+                        positions: _,
+                        column: _,
+                    },
+                column_name: _,
+                section: _,
+            } => ProblemCategory::ExtraCells,
+            Self::Line {
+                problem:
+                    LineProblem::MissingCell {
+                        line: _,
+                        positions: _,
+                        column: _,
+                    },
+                column_name: _,
+                section: _,
+            } => ProblemCategory::MissingCells,
+            Self::Row(RowProblem::ExtraRow {
                 line: _,
-                column: _,
-                expected: _,
-                actual: _,
-            }) => ProblemCategory::MismatchedCells,
-            Self::Line(LineProblem::ExtraCell { line: _, column: _ }) => {
-                ProblemCategory::ExtraCells
-            }
-            Self::Line(LineProblem::MissingCell { line: _, column: _ }) => {
-                ProblemCategory::MissingCells
-            }
+                row: _,
+                count: _,
+            }) => ProblemCategory::ExtraRows,
+            Self::Row(RowProblem::MissingRow {
+                line: _,
+                row: _,
+                count: _,
+            }) => ProblemCategory::MissingRows,
+            Self::Row(RowProblem::DuplicateRow {
+                expected_line: _,
+                actual_line: _,
+                row: _,
+                expected_count: _,
+                actual_count: _,
+            }) => ProblemCategory::DuplicateRows,
+            Self::Row(RowProblem::MovedRow {
+                from: _,
+                to: _,
+                row: _,
+            }) => ProblemCategory::MovedRows,
             Self::File(FileProblem::ExtraLines(_)) => ProblemCategory::ExtraLines,
             Self::File(FileProblem::MissingLines(_)) => ProblemCategory::MissingLines,
+            Self::File(FileProblem::DuplicateKey(_)) => ProblemCategory::DuplicateKeys,
+        }
+    }
+}
+
+impl Problem {
+    /// Describes the problem as its type, the color it is shown in and a sentence about it.
+    pub fn describe(&self) -> (&'static str, &'static str, String) {
+        match self {
+            Self::Line {
+                problem:
+                    LineProblem::MismatchedCell {
+                        line,
+                        positions,
+                        column,
+                        expected,
+                        actual,
+                        rewritten_by,
+                        json_path,
+                    },
+                column_name,
+                section,
+            } => (
+                "Mismatched cell",
+                "red",
+                format!(
+                    "The cell at {}, {} was {}, but the expected value was {}.{}{}",
+                    line_label(*line, *section, positions),
+                    column_label(*column, column_name),
+                    actual,
+                    expected,
+                    json_path
+                        .as_ref()
+                        .map(|path| format!("  They first differ at {}.", path))
+                        .unwrap_or_default(),
+                    rewritten_by
+                        .as_ref()
+                        .map(|rewrites| format!(
+                            "  The values were compared after applying {}.",
+                            rewrites
+                        ))
+                        .unwrap_or_default()
+                ),
+            ),
+            Self::Line {
+                problem:
+                    LineProblem::NullMismatch {
+                        line,
+                        positions,
+                        column,
+                        expected,
+                        actual,
+                    },
+                column_name,
+                section,
+            } => (
+                "Null mismatch",
+                "purple",
+                format!(
+                    "The cell at {}, {} was {}, but the expected value was {}, and only one of them is null.",
+                    line_label(*line, *section, positions),
+                    column_label(*column, column_name),
+                    display_value(actual),
+                    display_value(expected)
+                ),
+            ),
+            Self::Line {
+                problem:
+                    LineProblem::TypeMismatch {
+                        line,
+                        positions,
+                        column,
+                        expected,
+                        actual,
+                        column_type,
+                    },
+                column_name,
+                section,
+            } => (
+                "Type mismatch",
+                "brown",
+                format!(
+                    "The cell at {}, {} was {}, which is not {} like the expected value {}.",
+                    line_label(*line, *section, positions),
+                    column_label(*column, column_name),
+                    display_value(actual),
+                    column_type,
+                    expected
+                ),
+            ),
+            Self::Line {
+                problem:
+                    LineProblem::ExtraCell {
+                        line,
+                        positions,
+                        column,
+                    },
+                column_name,
+                section,
+            } => (
+                "Extra cell",
+                "orange",
+                format!(
+                    "The cell at {}, {} is not present in the expected file.",
+                    line_label(*line, *section, positions),
+                    column_label(*column, column_name)
+                ),
+            ),
+            Self::Line {
+                problem:
+                    LineProblem::MissingCell {
+                        line,
+                        positions,
+                        column,
+                    },
+                column_name,
+                section,
+            } => (
+                "Missing cell",
+                "yellow",
+                format!(
+                    "A cell is missing at {}, {}.",
+                    line_label(*line, *section, positions),
+                    column_label(*column, column_name)
+                ),
+            ),
+            Self::Row(RowProblem::ExtraRow { line, row, count }) => (
+                "Extra row",
+                "green",
+                format!(
+                    "The row {} at record {} of the actual file is not in the expected file.{}",
+                    row,
+                    line,
+                    times_label(*count)
+                ),
+            ),
+            Self::Row(RowProblem::MissingRow { line, row, count }) => (
+                "Missing row",
+                "blue",
+                format!(
+                    "The row {} at record {} of the expected file is not in the actual file.{}",
+                    row,
+                    line,
+                    times_label(*count)
+                ),
+            ),
+            Self::Row(RowProblem::DuplicateRow {
+                expected_line,
+                actual_line,
+                row,
+                expected_count,
+                actual_count,
+            }) => (
+                "Duplicate row",
+                "teal",
+                format!(
+                    "The row {} appears {} times in the actual file, starting at record {}, but {} times in the expected file, starting at record {}.",
+                    row, actual_count, actual_line, expected_count, expected_line
+                ),
+            ),
+            Self::Row(RowProblem::MovedRow { from, to, row }) => (
+                "Moved row",
+                "darkcyan",
+                format!(
+                    "The row {} at record {} of the expected file moved to record {} of the actual file.",
+                    row, from, to
+                ),
+            ),
+            Self::File(FileProblem::ExtraLines(ExtraLinesProblem { line, num_extra })) => (
+                "Extra line",
+                "green",
+                format!(
+                    "There were {} extra lines, starting with line {}.",
+                    num_extra, line
+                ),
+            ),
+            Self::File(FileProblem::MissingLines(MissingLinesProblem { line, num_missing })) => (
+                "Missing line",
+                "blue",
+                format!(
+                    "There were {} lines missing, ending at line {}.",
+                    num_missing, line
+                ),
+            ),
+            Self::File(FileProblem::DuplicateKey(DuplicateKeyProblem {
+                key,
+                expected_lines,
+                actual_lines,
+            })) => (
+                "Duplicate key",
+                "maroon",
+                format!(
+                    "The key {} is shared by {}.",
+                    key,
+                    records_label(expected_lines, "expected")
+                        .into_iter()
+                        .chain(records_label(actual_lines, "actual"))
+                        .join(" and ")
+                ),
+            ),
+        }
+    }
+
+    /// The changes that turn the expected value into the actual one, for a cell that doesn't match.
+    pub fn cell_diff(&self) -> Option<Vec<Segment>> {
+        match self {
+            Self::Line {
+                problem:
+                    LineProblem::MismatchedCell {
+                        line: _,
+                        positions: _,
+                        column: _,
+                        expected,
+                        actual,
+                        rewritten_by: _,
+                        json_path: _,
+                    },
+                column_name: _,
+                section: _,
+            } => Some(cell_diff::diff(expected, actual)),
+            _ => None,
         }
     }
 }
@@ -134,73 +909,49 @@ impl Serialize for Problem {
     where
         S: Serializer,
     {
-        let mut problem = serializer.serialize_map(Some(3))?;
-        match self {
-            Self::Line(LineProblem::MismatchedCell {
-                line,
-                column,
-                expected,
-                actual,
-            }) => {
-                problem.serialize_entry("type", "Mismatched cell")?;
-                problem.serialize_entry("color", "red")?;
-                problem.serialize_entry(
-                    "description",
-                    &format!(
-                        "The cell at line {}, column {} was {}, but the expected value was {}.",
-                        line, column, actual, expected
-                    ),
-                )?;
-            }
-            Self::Line(LineProblem::ExtraCell { line, column }) => {
-                problem.serialize_entry("type", "Extra cell")?;
-                problem.serialize_entry("color", "orange")?;
-                problem.serialize_entry(
-                    "description",
-                    &format!(
-                        "The cell at line {}, column {} is not present in the expected file.",
-                        line, column
-                    ),
-                )?;
-            }
-            Self::Line(LineProblem::MissingCell { line, column }) => {
-                problem.serialize_entry("type", "Missing cell")?;
-                problem.serialize_entry("color", "yellow")?;
-                problem.serialize_entry(
-                    "description",
-                    &format!("A cell is missing at line {}, column {}.", line, column),
-                )?;
-            }
-            Self::File(FileProblem::ExtraLines(ExtraLinesProblem { line, num_extra })) => {
-                problem.serialize_entry("type", "Extra line")?;
-                problem.serialize_entry("color", "green")?;
-                problem.serialize_entry(
-                    "description",
-                    &format!(
-                        "There were {} extra lines, starting with line {}.",
-                        num_extra, line
-                    ),
-                )?;
-            }
-            Self::File(FileProblem::MissingLines(MissingLinesProblem { line, num_missing })) => {
-                problem.serialize_entry("type", "Missing line")?;
-                problem.serialize_entry("color", "blue")?;
-                problem.serialize_entry(
-                    "description",
-                    &format!(
-                        "There were {} lines missing, ending at line {}.",
-                        num_missing, line
-                    ),
-                )?;
-            }
-        };
+        let mut problem = serializer.serialize_map(None)?;
+        let (problem_type, color, description) = self.describe();
+        problem.serialize_entry("type", problem_type)?;
+        problem.serialize_entry("color", color)?;
+        problem.serialize_entry("description", &description)?;
+        if let Some(cell_diff) = self.cell_diff() {
+            problem.serialize_entry("cell_diff", &cell_diff)?;
+        }
+        if let Self::Line {
+            problem: line_problem,
+            column_name: _,
+            section: _,
+        } = self
+        {
+            problem.serialize_entry("position", &line_problem.positions().bytes_description())?;
+        }
         problem.end()
     }
 }
 
+/// The filter rows had to match to be compared, and how many rows of each file it left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterSummary {
+    pub expression: String,
+    pub expected_excluded: usize,
+    pub actual_excluded: usize,
+}
+
+/// How many problems were left out for being approved in a baseline file, and the approved problems that didn't
+/// happen this time.
+#[derive(Debug, Clone, Serialize)]
+pub struct BaselineSummary {
+    pub num_approved: usize,
+    pub no_longer_found: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct DisplayProblems {
     actual_filename: String,
+    expected_sheet: Option<String>,
+    actual_sheet: Option<String>,
+    filter: Option<FilterSummary>,
+    baseline: Option<BaselineSummary>,
     num_problems: usize,
     found_max_problems: bool,
     found_any_problems: bool,
@@ -214,37 +965,44 @@ pub struct Problems {
     extra_lines_problem: Option<ExtraLinesProblem>,
     missing_lines_problem: Option<MissingLinesProblem>,
     line_problems: Vec<LineProblem>,
+    trailer_problems: Vec<LineProblem>,
+    row_problems: Vec<RowProblem>,
+    duplicate_key_problems: Vec<DuplicateKeyProblem>,
+    column_names: Vec<String>,
+    filter_summary: Option<FilterSummary>,
+    baseline_summary: Option<BaselineSummary>,
 }
 
 pub struct DisplayableProblems<I> {
     line_problems_to_display: usize,
     extra_lines_problem: Option<ExtraLinesProblem>,
     missing_lines_problem: Option<MissingLinesProblem>,
+    duplicate_key_problems: std::vec::IntoIter<DuplicateKeyProblem>,
     iter: I,
 }
 
-impl<'a, I> Iterator for DisplayableProblems<I>
+impl<I> Iterator for DisplayableProblems<I>
 where
-    I: Iterator<Item = &'a LineProblem>,
+    I: Iterator<Item = Problem>,
 {
     type Item = Problem;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.line_problems_to_display > 0 {
             self.line_problems_to_display -= 1;
-            self.iter
-                .next()
-                .map(|line_problem| Problem::Line(line_problem.clone()))
-        } else if let Some(extra_lines_problem) = &self.extra_lines_problem {
-            Some(Problem::File(FileProblem::ExtraLines(
-                extra_lines_problem.clone(),
-            )))
-        } else if let Some(missing_lines_problem) = &self.missing_lines_problem {
-            Some(Problem::File(FileProblem::MissingLines(
-                missing_lines_problem.clone(),
+            self.iter.next()
+        } else if let Some(duplicate_key_problem) = self.duplicate_key_problems.next() {
+            Some(Problem::File(FileProblem::DuplicateKey(
+                duplicate_key_problem,
             )))
+        } else if let Some(extra_lines_problem) = self.extra_lines_problem.take() {
+            Some(Problem::File(FileProblem::ExtraLines(extra_lines_problem)))
         } else {
-            None
+            self.missing_lines_problem
+                .take()
+                .map(|missing_lines_problem| {
+                    Problem::File(FileProblem::MissingLines(missing_lines_problem))
+                })
         }
     }
 }
@@ -256,11 +1014,121 @@ impl Problems {
             extra_lines_problem: None,
             missing_lines_problem: None,
             line_problems: vec![],
+            trailer_problems: vec![],
+            row_problems: vec![],
+            duplicate_key_problems: vec![],
+            column_names: vec![],
+            filter_summary: None,
+            baseline_summary: None,
         }
     }
 
+    /// Names the columns, so problems can be described by the name of their column as well as its number.
+    pub fn set_column_names(&mut self, column_names: Vec<String>) {
+        self.column_names = column_names;
+    }
+
+    pub fn set_filter_summary(&mut self, filter_summary: FilterSummary) {
+        self.filter_summary = Some(filter_summary);
+    }
+
+    pub fn filter_summary(&self) -> Option<&FilterSummary> {
+        self.filter_summary.as_ref()
+    }
+
+    pub fn baseline_summary(&self) -> Option<&BaselineSummary> {
+        self.baseline_summary.as_ref()
+    }
+
+    /// Every problem found, remembered for a baseline file.
+    pub fn baseline_entries(&self) -> Vec<BaselineEntry> {
+        let mut entries: Vec<BaselineEntry> = self
+            .line_problems
+            .iter()
+            .map(|problem| problem.baseline_entry(Section::Body))
+            .chain(
+                self.trailer_problems
+                    .iter()
+                    .map(|problem| problem.baseline_entry(Section::Trailer)),
+            )
+            .chain(self.row_problems.iter().map(RowProblem::baseline_entry))
+            .chain(
+                self.duplicate_key_problems
+                    .iter()
+                    .map(DuplicateKeyProblem::baseline_entry),
+            )
+            .collect();
+        entries.extend(
+            self.extra_lines_problem
+                .as_ref()
+                .map(ExtraLinesProblem::baseline_entry),
+        );
+        entries.extend(
+            self.missing_lines_problem
+                .as_ref()
+                .map(MissingLinesProblem::baseline_entry),
+        );
+        entries
+    }
+
+    /// Leaves out the problems approved in a baseline file.  Each entry in the baseline approves one problem, and the
+    /// entries that approve nothing are kept to be reported.
+    pub fn subtract_baseline(&mut self, baseline: Vec<BaselineEntry>) {
+        let mut remaining: HashMap<BaselineEntry, usize> = HashMap::new();
+        for entry in baseline {
+            *remaining.entry(entry).or_default() += 1;
+        }
+        let mut num_approved = 0;
+        let mut approve = |entry: BaselineEntry| match remaining.get_mut(&entry) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                num_approved += 1;
+                true
+            }
+            _ => false,
+        };
+
+        self.line_problems
+            .retain(|problem| !approve(problem.baseline_entry(Section::Body)));
+        self.trailer_problems
+            .retain(|problem| !approve(problem.baseline_entry(Section::Trailer)));
+        self.row_problems
+            .retain(|problem| !approve(problem.baseline_entry()));
+        self.duplicate_key_problems
+            .retain(|problem| !approve(problem.baseline_entry()));
+        if self
+            .extra_lines_problem
+            .as_ref()
+            .is_some_and(|problem| approve(problem.baseline_entry()))
+        {
+            self.extra_lines_problem = None;
+        }
+        if self
+            .missing_lines_problem
+            .as_ref()
+            .is_some_and(|problem| approve(problem.baseline_entry()))
+        {
+            self.missing_lines_problem = None;
+        }
+
+        let no_longer_found = remaining
+            .into_iter()
+            .flat_map(|(entry, count)| std::iter::repeat_n(entry.to_string(), count))
+            .sorted()
+            .collect();
+        self.baseline_summary = Some(BaselineSummary {
+            num_approved,
+            no_longer_found,
+        });
+    }
+
+    fn num_line_problems(&self) -> usize {
+        self.line_problems.len() + self.trailer_problems.len() + self.row_problems.len()
+    }
+
     pub fn len(&self) -> usize {
-        self.line_problems.len()
+        self.num_line_problems()
+            + self.duplicate_key_problems.len()
             + self.extra_lines_problem.as_ref().map(|_| 1).unwrap_or(0)
             + self.missing_lines_problem.as_ref().map(|_| 1).unwrap_or(0)
     }
@@ -290,24 +1158,80 @@ impl Problems {
         }
     }
 
-    pub fn insert_line_problem(&mut self, problem: LineProblem) {
-        self.line_problems.push(problem);
+    pub fn insert_duplicate_key_problem(
+        &mut self,
+        key: String,
+        expected_lines: Vec<usize>,
+        actual_lines: Vec<usize>,
+    ) {
+        self.duplicate_key_problems.push(DuplicateKeyProblem {
+            key,
+            expected_lines,
+            actual_lines,
+        });
+    }
+
+    /// Forgets the problems found in the body of the files, for when it turns out its rows couldn't be paired up.
+    pub fn discard_body_problems(&mut self) {
+        self.line_problems.clear();
+        self.row_problems.clear();
+    }
+
+    pub fn insert_line_problem(&mut self, section: Section, problem: LineProblem) {
+        match section {
+            Section::Body => self.line_problems.push(problem),
+            Section::Trailer => self.trailer_problems.push(problem),
+        }
+    }
+
+    /// Adds problems with whole rows, keeping all of them in the order the rows first appear.
+    pub fn insert_row_problems(&mut self, problems: impl Iterator<Item = RowProblem>) {
+        self.row_problems.extend(problems);
+        self.row_problems.sort_by_key(|problem| problem.line());
     }
 
-    pub fn displayable_problems(&self) -> DisplayableProblems<std::slice::Iter<LineProblem>> {
+    pub fn displayable_problems(&self) -> DisplayableProblems<impl Iterator<Item = Problem> + '_> {
         let line_problems_to_display = min(
-            self.line_problems.len() as usize,
-            self.max_problems_to_display - (self.len() - self.line_problems.len()),
+            self.num_line_problems(),
+            self.max_problems_to_display
+                .saturating_sub(self.len() - self.num_line_problems()),
         );
+        let column_names = &self.column_names;
         DisplayableProblems {
             line_problems_to_display,
             extra_lines_problem: self.extra_lines_problem.clone(),
             missing_lines_problem: self.missing_lines_problem.clone(),
-            iter: self.line_problems.iter(),
+            duplicate_key_problems: self
+                .duplicate_key_problems
+                .iter()
+                .take(self.max_problems_to_display)
+                .cloned()
+                .collect::<Vec<_>>()
+                .into_iter(),
+            iter: self
+                .line_problems
+                .iter()
+                .map(|problem| (Section::Body, problem))
+                .chain(
+                    self.trailer_problems
+                        .iter()
+                        .map(|problem| (Section::Trailer, problem)),
+                )
+                .map(move |(section, line_problem)| Problem::Line {
+                    problem: line_problem.clone(),
+                    column_name: column_names.get(line_problem.column() - 1).cloned(),
+                    section,
+                })
+                .chain(self.row_problems.iter().cloned().map(Problem::Row)),
         }
     }
 
-    pub fn display_data(&self, actual_filename: &str) -> DisplayProblems {
+    pub fn display_data(
+        &self,
+        actual_filename: &str,
+        expected_sheet: Option<&str>,
+        actual_sheet: Option<&str>,
+    ) -> DisplayProblems {
         let mut categories = HashSet::new();
         let mut problems = vec![];
 
@@ -318,6 +1242,10 @@ impl Problems {
 
         DisplayProblems {
             actual_filename: actual_filename.to_string(),
+            expected_sheet: expected_sheet.map(|sheet| sheet.to_string()),
+            actual_sheet: actual_sheet.map(|sheet| sheet.to_string()),
+            filter: self.filter_summary.clone(),
+            baseline: self.baseline_summary.clone(),
             num_problems: self.len(),
             found_max_problems: self.len() >= self.max_problems_to_display,
             found_any_problems: self.len() > 0,
@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+
+use regex::Regex;
+
+use crate::columns::ColumnSelector;
+
+/// A rule that replaces whatever matches a pattern in a column's cells before they are compared, like turning `1.0`
+/// into `1`.  Rules are numbered from 1 in the order they were given, so reports can say which one changed a value.
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    pub number: usize,
+    pub column: ColumnSelector,
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl Rewrite {
+    pub fn new(
+        number: usize,
+        column: &str,
+        pattern: &str,
+        replacement: &str,
+    ) -> Result<Self, regex::Error> {
+        Ok(Rewrite {
+            number,
+            column: ColumnSelector::parse(column),
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+        })
+    }
+
+    pub fn apply<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        self.pattern.replace_all(value, self.replacement.as_str())
+    }
+}
+
+impl Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "rewrite rule {} ({} to {})",
+            self.number, self.pattern, self.replacement
+        )
+    }
+}
+
+/// Finds the rules for each column, given the names of the columns, keeping them in the order they were given.
+pub fn for_columns(rewrites: &[Rewrite], column_names: &[String]) -> Vec<Vec<Rewrite>> {
+    let mut columns = vec![vec![]; column_names.len()];
+    for rewrite in rewrites {
+        if let Some(column) = rewrite.column.resolve(column_names) {
+            if let Some(rules) = columns.get_mut(column) {
+                rules.push(rewrite.clone());
+            }
+        }
+    }
+    columns
+}
@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use clap::arg_enum;
+
+use super::{SkipOptions, SkippedLines, Source, SourceError};
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Delimiter {
+        Comma,
+        Pipe,
+        Tab
+    }
+}
+
+impl Delimiter {
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Delimiter::Comma => b',',
+            Delimiter::Pipe => b'|',
+            Delimiter::Tab => b'\t',
+        }
+    }
+}
+
+pub struct DelimitedSource {
+    headers: csv::StringRecord,
+    records: csv::StringRecordsIntoIter<SkippedLines<BufReader<File>>>,
+}
+
+impl DelimitedSource {
+    pub fn open<P: AsRef<Path>>(
+        filepath: P,
+        delimiter: Delimiter,
+        skip: &SkipOptions,
+    ) -> Result<Self, SourceError> {
+        let mut reader = csv::ReaderBuilder::new()
+            // With the expected file as the source of truth, we can't assume that it has a consistent number of rows.
+            // The flexible option ensures that doesn't surface as an error.
+            .flexible(true)
+            .delimiter(delimiter.as_byte())
+            .from_reader(SkippedLines::open(filepath, skip)?);
+        let headers = reader.headers()?.clone();
+        Ok(DelimitedSource {
+            headers,
+            records: reader.into_records(),
+        })
+    }
+}
+
+impl Iterator for DelimitedSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = match self.records.next()? {
+            Ok(record) => record,
+            Err(error) => return Some(Err(error.into())),
+        };
+        let position = record.position().map(|position| {
+            self.records
+                .reader()
+                .get_ref()
+                .original_record_position(position)
+        });
+        record.set_position(position);
+        Some(Ok(record))
+    }
+}
+
+impl Source for DelimitedSource {
+    fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+}
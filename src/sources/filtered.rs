@@ -0,0 +1,63 @@
+use super::{ColumnType, SharedColumns, Source, SourceError};
+use crate::filter::Condition;
+
+/// Passes on only the records of another source that meet a condition, counting the ones it leaves out.  The trailer
+/// is passed on whole.
+pub struct FilteredSource<'a> {
+    inner: &'a mut dyn Source,
+    condition: Condition<usize>,
+    pub excluded: usize,
+}
+
+impl<'a> FilteredSource<'a> {
+    pub fn new(inner: &'a mut dyn Source, condition: Condition<usize>) -> Self {
+        FilteredSource {
+            inner,
+            condition,
+            excluded: 0,
+        }
+    }
+}
+
+impl Iterator for FilteredSource<'_> {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next()? {
+                Ok(record) if !self.condition.accepts(&record) => self.excluded += 1,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+impl Source for FilteredSource<'_> {
+    fn headers(&self) -> &csv::StringRecord {
+        self.inner.headers()
+    }
+
+    fn column_type(&self, column: usize) -> Option<ColumnType> {
+        self.inner.column_type(column)
+    }
+
+    fn sheet_name(&self) -> Option<&str> {
+        self.inner.sheet_name()
+    }
+
+    fn align_to(&mut self, headers: &csv::StringRecord) {
+        self.inner.align_to(headers)
+    }
+
+    fn named_columns(&self) -> Option<SharedColumns> {
+        self.inner.named_columns()
+    }
+
+    fn share_named_columns(&mut self, columns: SharedColumns) {
+        self.inner.share_named_columns(columns)
+    }
+
+    fn trailer(&self) -> &[csv::StringRecord] {
+        self.inner.trailer()
+    }
+}
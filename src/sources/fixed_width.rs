@@ -0,0 +1,144 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{PositionedLines, SkipOptions, SkippedLines, Source, SourceError};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Trim {
+    #[default]
+    Both,
+    Left,
+    Right,
+    None,
+}
+
+/// A field in a fixed-width layout.  Positions are counted in characters, starting from 1.
+#[derive(Debug, Deserialize)]
+struct Field {
+    name: String,
+    start: usize,
+    length: usize,
+    trim: Option<Trim>,
+}
+
+impl Field {
+    fn extract(&self, line: &[char]) -> Option<String> {
+        let start = self.start - 1;
+        if start >= line.len() {
+            return None;
+        }
+        let end = min(start + self.length, line.len());
+        let value: String = line[start..end].iter().collect();
+        Some(match self.trim.unwrap_or_default() {
+            Trim::Both => value.trim().to_string(),
+            Trim::Left => value.trim_start().to_string(),
+            Trim::Right => value.trim_end().to_string(),
+            Trim::None => value,
+        })
+    }
+}
+
+/// Reads a file of fixed-width records, cutting each line into fields according to a layout.
+///
+/// The layout is a CSV file with a `name`, `start` and `length` for each field, and optionally how to `trim` it
+/// (`both`, `left`, `right` or `none`, defaulting to `both`).  The fields become the columns, in the order the layout
+/// lists them.
+pub struct FixedWidthSource {
+    lines: PositionedLines<BufReader<SkippedLines<BufReader<File>>>>,
+    num_records: u64,
+    headers: csv::StringRecord,
+    fields: Vec<Field>,
+}
+
+fn read_layout<P: AsRef<Path>>(layout_filepath: P) -> Result<Vec<Field>, SourceError> {
+    let layout_filepath = layout_filepath.as_ref();
+    let invalid = |message: String| {
+        SourceError::Layout(format!("{}: {}", layout_filepath.display(), message))
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(layout_filepath)
+        .map_err(|error| invalid(error.to_string()))?;
+    let headers = reader
+        .headers()
+        .map_err(|error| invalid(error.to_string()))?
+        .clone();
+    let mut fields = vec![];
+    for record in reader.records() {
+        let mut record = record.map_err(|error| invalid(error.to_string()))?;
+        // Leaving off the trim is the same as leaving it empty.
+        while record.len() < headers.len() {
+            record.push_field("");
+        }
+        let field: Field = record
+            .deserialize(Some(&headers))
+            .map_err(|error| invalid(error.to_string()))?;
+        if field.start == 0 {
+            return Err(invalid(format!(
+                "the field {} starts at 0, but positions start from 1",
+                field.name
+            )));
+        }
+        fields.push(field);
+    }
+    if fields.is_empty() {
+        return Err(invalid("the layout has no fields".to_string()));
+    }
+    Ok(fields)
+}
+
+impl FixedWidthSource {
+    pub fn open<P: AsRef<Path>, L: AsRef<Path>>(
+        filepath: P,
+        layout_filepath: L,
+        skip: &SkipOptions,
+    ) -> Result<Self, SourceError> {
+        let fields = read_layout(layout_filepath)?;
+        Ok(FixedWidthSource {
+            lines: PositionedLines::new(BufReader::new(SkippedLines::open(filepath, skip)?)),
+            num_records: 0,
+            headers: fields.iter().map(|field| field.name.as_str()).collect(),
+            fields,
+        })
+    }
+}
+
+impl Iterator for FixedWidthSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Blank lines hold no fields, so they're passed over like they are in the other formats.
+        let (line, position) = match self
+            .lines
+            .find(|line| !matches!(line, Ok((line, _)) if line.is_empty()))?
+        {
+            Ok(line) => line,
+            Err(error) => return Some(Err(error.into())),
+        };
+        let line: Vec<char> = line.chars().collect();
+        // A line that ends before a field starts is missing that field and the ones after it.
+        let mut record: csv::StringRecord = self
+            .fields
+            .iter()
+            .map_while(|field| field.extract(&line))
+            .collect();
+        let mut position = self.lines.get_ref().get_ref().original_position(&position);
+        position.set_record(self.num_records);
+        self.num_records += 1;
+        record.set_position(Some(position));
+        Some(Ok(record))
+    }
+}
+
+impl Source for FixedWidthSource {
+    fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::de::Error;
+use serde_json::{Map, Value};
+
+use super::{
+    NamedColumns, PositionedLines, SharedColumns, SkipOptions, SkippedLines, Source, SourceError,
+};
+
+/// An object along with where its line starts in the file.
+type PositionedObject = (Map<String, Value>, csv::Position);
+
+/// Reads a file with one JSON object per line, flattening each object into a record.  Nested objects become columns
+/// named by their dotted path (`{"a": {"b": 1}}` becomes a column `a.b`), and the columns are laid out in the order
+/// their keys are first seen.
+pub struct JsonLinesSource {
+    lines: PositionedLines<BufReader<SkippedLines<BufReader<File>>>>,
+    num_records: u64,
+    columns: NamedColumns,
+    pending: Option<PositionedObject>,
+}
+
+fn flatten_into(prefix: &str, object: Map<String, Value>, cells: &mut Vec<(String, String)>) {
+    for (key, value) in object {
+        let path = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Value::Object(nested) => flatten_into(&path, nested, cells),
+            Value::Null => cells.push((path, String::new())),
+            Value::String(string) => cells.push((path, string)),
+            // Numbers, booleans and arrays are kept as their JSON text.
+            other => cells.push((path, other.to_string())),
+        }
+    }
+}
+
+impl JsonLinesSource {
+    pub fn open<P: AsRef<Path>>(filepath: P, skip: &SkipOptions) -> Result<Self, SourceError> {
+        let mut source = JsonLinesSource {
+            lines: PositionedLines::new(BufReader::new(SkippedLines::open(filepath, skip)?)),
+            num_records: 0,
+            columns: NamedColumns::default(),
+            pending: None,
+        };
+        // The columns come from the keys of the objects, so the first one has to be read up front.
+        if let Some(first) = source.next_object() {
+            let (first, position) = first?;
+            let mut cells = vec![];
+            flatten_into("", first.clone(), &mut cells);
+            source.columns = NamedColumns::new(cells.iter().map(|(column, _)| column.as_str()));
+            source.pending = Some((first, position));
+        }
+        Ok(source)
+    }
+
+    fn next_object(&mut self) -> Option<Result<PositionedObject, SourceError>> {
+        for line in &mut self.lines {
+            let (line, position) = match line {
+                Ok(line) => line,
+                Err(error) => return Some(Err(error.into())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_number = position.line() as usize;
+            return Some(match serde_json::from_str(&line) {
+                Ok(Value::Object(object)) => Ok((object, position)),
+                Ok(_) => Err(SourceError::Json {
+                    line: line_number,
+                    error: serde_json::Error::custom("expected a JSON object"),
+                }),
+                Err(error) => Err(SourceError::Json {
+                    line: line_number,
+                    error,
+                }),
+            });
+        }
+        None
+    }
+
+    fn record_from(
+        &mut self,
+        object: Map<String, Value>,
+        position: csv::Position,
+    ) -> csv::StringRecord {
+        let mut flattened = vec![];
+        flatten_into("", object, &mut flattened);
+
+        let mut record = self.columns.record_from(flattened);
+        let mut position = self.lines.get_ref().get_ref().original_position(&position);
+        position.set_record(self.num_records);
+        self.num_records += 1;
+        record.set_position(Some(position));
+        record
+    }
+}
+
+impl Iterator for JsonLinesSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (object, position) = match self.pending.take() {
+            Some(pending) => pending,
+            None => match self.next_object()? {
+                Ok(object) => object,
+                Err(error) => return Some(Err(error)),
+            },
+        };
+        Some(Ok(self.record_from(object, position)))
+    }
+}
+
+impl Source for JsonLinesSource {
+    fn headers(&self) -> &csv::StringRecord {
+        self.columns.headers()
+    }
+
+    fn align_to(&mut self, headers: &csv::StringRecord) {
+        self.columns.align_to(headers)
+    }
+
+    fn named_columns(&self) -> Option<SharedColumns> {
+        Some(self.columns.shared())
+    }
+
+    fn share_named_columns(&mut self, columns: SharedColumns) {
+        self.columns.share(columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn open(contents: &str) -> (JsonLinesSource, tempfile::NamedTempFile) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        (
+            JsonLinesSource::open(file.path(), &SkipOptions::default()).unwrap(),
+            file,
+        )
+    }
+
+    fn cells(record: Option<Result<csv::StringRecord, SourceError>>) -> Vec<String> {
+        record.unwrap().unwrap().iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn lines_up_keys_first_seen_on_either_side() {
+        let (mut expected, _expected_file) = open("{\"a\": 1}\n{\"a\": 2, \"b\": \"x\"}\n");
+        let (mut actual, _actual_file) = open("{\"a\": 1}\n{\"c\": \"y\", \"a\": 2}\n");
+        actual.align_to(&expected.headers().clone());
+        actual.share_named_columns(expected.named_columns().unwrap());
+
+        assert_eq!(cells(expected.next()), vec!["1"]);
+        assert_eq!(cells(actual.next()), vec!["1"]);
+        assert_eq!(cells(expected.next()), vec!["2", "x"]);
+        assert_eq!(cells(actual.next()), vec!["2", "", "y"]);
+        assert_eq!(expected.headers(), &csv::StringRecord::from(vec!["a", "b"]));
+        assert_eq!(
+            actual.headers(),
+            &csv::StringRecord::from(vec!["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn keeps_columns_seen_before_sharing() {
+        let (mut expected, _expected_file) = open("{\"a\": 1, \"b\": 2}\n{\"d\": 4}\n");
+        let (mut actual, _actual_file) = open("{\"c\": 3, \"b\": 2}\n{\"d\": 4}\n");
+        actual.align_to(&expected.headers().clone());
+        actual.share_named_columns(expected.named_columns().unwrap());
+
+        assert_eq!(
+            actual.headers(),
+            &csv::StringRecord::from(vec!["a", "b", "c"])
+        );
+        assert_eq!(cells(expected.next()), vec!["1", "2"]);
+        assert_eq!(cells(actual.next()), vec!["", "2", "3"]);
+        assert_eq!(cells(expected.next()), vec!["", "", "", "4"]);
+        assert_eq!(cells(actual.next()), vec!["", "", "", "4"]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::{self, BufRead};
+
+/// Iterates over the lines of a text file along with where each one starts, with the line endings taken off.
+pub struct PositionedLines<R> {
+    inner: R,
+    line_number: u64,
+    byte: u64,
+}
+
+impl<R: BufRead> PositionedLines<R> {
+    pub fn new(inner: R) -> Self {
+        PositionedLines {
+            inner,
+            line_number: 0,
+            byte: 0,
+        }
+    }
+}
+
+impl<R> PositionedLines<R> {
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: BufRead> Iterator for PositionedLines<R> {
+    type Item = io::Result<(String, csv::Position)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        let len = match self.inner.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(len) => len,
+            Err(error) => return Some(Err(error)),
+        };
+        self.line_number += 1;
+        let mut position = csv::Position::new();
+        position.set_line(self.line_number).set_byte(self.byte);
+        self.byte += len as u64;
+
+        let content_len = line.trim_end_matches(&['\r', '\n'][..]).len();
+        line.truncate(content_len);
+        Some(Ok((line, position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor, Write};
+
+    use regex::bytes::Regex;
+
+    use super::*;
+    use crate::sources::{SkipOptions, SkippedLines};
+
+    /// Each line that isn't blank, along with the line and byte it starts at in the original file.
+    fn lines(contents: &str, options: SkipOptions) -> Vec<(String, u64, u64)> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        let mut lines = PositionedLines::new(BufReader::new(
+            SkippedLines::open(file.path(), &options).unwrap(),
+        ));
+        let mut found = vec![];
+        while let Some(line) = lines.next() {
+            let (line, position) = line.unwrap();
+            if line.is_empty() {
+                continue;
+            }
+            let position = lines.get_ref().get_ref().original_position(&position);
+            found.push((line, position.line(), position.byte()));
+        }
+        found
+    }
+
+    fn line(line: &str, line_number: u64, byte: u64) -> (String, u64, u64) {
+        (line.to_string(), line_number, byte)
+    }
+
+    #[test]
+    fn takes_off_line_endings() {
+        let positions: Vec<_> = PositionedLines::new(Cursor::new("a\r\nbc\n\nd"))
+            .map(|line| {
+                let (line, position) = line.unwrap();
+                (line, position.line(), position.byte())
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                line("a", 1, 0),
+                line("bc", 2, 3),
+                line("", 3, 6),
+                line("d", 4, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_lines_after_leading_lines() {
+        let options = SkipOptions {
+            leading_lines: 2,
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            lines("Title\nGenerated\n{\"a\": 1}\n{\"a\": 2}\n", options),
+            vec![line("{\"a\": 1}", 3, 16), line("{\"a\": 2}", 4, 25)]
+        );
+    }
+
+    #[test]
+    fn finds_lines_after_prefixed_and_matching_lines() {
+        let options = SkipOptions {
+            prefix: Some("#".to_string()),
+            pattern: Some(Regex::new("^SKIP").unwrap()),
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            lines("# note\nfirst\nSKIP me\n\n# more\nsecond\n", options),
+            vec![line("first", 2, 7), line("second", 6, 29)]
+        );
+    }
+
+    #[test]
+    fn counts_both_bytes_of_crlf_line_endings() {
+        let options = SkipOptions {
+            leading_lines: 1,
+            prefix: Some("#".to_string()),
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            lines("Title\r\nfirst\r\n# c\r\nsecond\r\n", options),
+            vec![line("first", 2, 7), line("second", 4, 19)]
+        );
+    }
+}
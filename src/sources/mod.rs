@@ -0,0 +1,492 @@
+mod delimited;
+mod filtered;
+mod fixed_width;
+mod jsonl;
+mod lines;
+mod named;
+mod parquet;
+mod recorded;
+mod skip;
+mod spreadsheet;
+mod sqlite;
+mod trailer;
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use clap::arg_enum;
+
+use crate::json::{self, JsonComparison};
+
+pub use self::parquet::ParquetSource;
+pub use delimited::{DelimitedSource, Delimiter};
+pub use filtered::FilteredSource;
+pub use fixed_width::FixedWidthSource;
+pub use jsonl::JsonLinesSource;
+pub use lines::PositionedLines;
+pub use named::{NamedColumns, SharedColumns};
+pub use recorded::RecordedSource;
+pub use skip::{SkipOptions, SkippedLines};
+pub use spreadsheet::SpreadsheetSource;
+pub use sqlite::{SqliteQuery, SqliteSource};
+pub use trailer::TrailerSource;
+
+arg_enum! {
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub enum Format {
+        Csv,
+        Jsonl,
+        Parquet,
+        Xlsx,
+        FixedWidth,
+        Sqlite
+    }
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension, falling back to CSV.
+    pub fn from_path<P: AsRef<Path>>(filepath: P) -> Format {
+        let extension = filepath
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("jsonl") | Some("ndjson") => Format::Jsonl,
+            Some("parquet") | Some("pq") => Format::Parquet,
+            Some("xlsx") | Some("xlsm") | Some("xls") | Some("ods") => Format::Xlsx,
+            Some("db") | Some("sqlite") | Some("sqlite3") => Format::Sqlite,
+            _ => Format::Csv,
+        }
+    }
+
+    /// Whether the columns of a file in this format are matched up with the other file's by name, rather than by
+    /// position.
+    pub fn matches_columns_by_name(self) -> bool {
+        match self {
+            Format::Csv | Format::Xlsx | Format::FixedWidth | Format::Sqlite => false,
+            Format::Jsonl | Format::Parquet => true,
+        }
+    }
+}
+
+/// The type of the values in a column, for sources that know it, or as guessed from the values in the expected file.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ColumnType {
+    Text,
+    Integer,
+    Decimal,
+    Boolean,
+    Date,
+    Json,
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Integer => write!(f, "an integer"),
+            Self::Decimal => write!(f, "a decimal"),
+            Self::Boolean => write!(f, "a boolean"),
+            Self::Date => write!(f, "a date"),
+            Self::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+fn parse_bool(cell: &str) -> Option<bool> {
+    match cell.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+const DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%Y/%m/%d"];
+const DATE_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.fZ",
+];
+
+/// Reads a date, or a date and time, in one of the year-first formats files commonly use.
+pub fn parse_date(cell: &str) -> Option<NaiveDateTime> {
+    let cell = cell.trim();
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(cell, format).ok())
+        .map(|date| date.and_time(NaiveTime::MIN))
+        .or_else(|| {
+            DATE_TIME_FORMATS
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(cell, format).ok())
+        })
+}
+
+/// The most significant digits a decimal can have and still be read without losing any of them.
+const MAX_DECIMAL_DIGITS: usize = 15;
+
+/// Whether a cell is a number written out in digits, rather than as `inf` or `NaN`, with few enough significant
+/// digits to be read as a decimal exactly.
+fn is_exact_decimal(cell: &str) -> bool {
+    let cell = cell.trim();
+    if !cell
+        .chars()
+        .all(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+        || cell.parse::<f64>().is_err()
+    {
+        return false;
+    }
+    let mantissa = cell.split(['e', 'E']).next().unwrap_or_default();
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    digits.trim_start_matches('0').trim_end_matches('0').len() <= MAX_DECIMAL_DIGITS
+}
+
+/// Writes a number so that numbers that match are written the same way: whole numbers without a decimal point, and
+/// others in the shortest form that reads back as the same decimal.
+pub fn canonical_number(cell: &str) -> Option<String> {
+    let cell = cell.trim();
+    if let Ok(integer) = cell.parse::<i128>() {
+        return Some(integer.to_string());
+    }
+    let number = cell.parse::<f64>().ok()?;
+    Some(if number.is_nan() {
+        "NaN".to_string()
+    } else if number.fract() == 0.0 && number.abs() < 1e38 {
+        (number as i128).to_string()
+    } else {
+        format!("{:?}", number)
+    })
+}
+
+/// Whether a number is written with a zero before its other digits, like `007` or `-01.5`, which is how codes are
+/// written rather than numbers.
+fn has_leading_zero(cell: &str) -> bool {
+    let digits = cell.trim().trim_start_matches(['+', '-']).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+fn is_non_finite(cell: &str) -> bool {
+    cell.trim()
+        .parse::<f64>()
+        .is_ok_and(|value| !value.is_finite())
+}
+
+impl ColumnType {
+    /// Whether two cells hold the same value of this type.  Cells that can't be read as this type are compared as
+    /// text, and identical cells always match.  Whole numbers are compared exactly, since large ones lose precision
+    /// as decimals, and NaN matches NaN however it is written.
+    pub fn cells_match(self, expected: &str, actual: &str) -> bool {
+        if expected == actual {
+            return true;
+        }
+        match self {
+            Self::Text => false,
+            Self::Integer | Self::Decimal => {
+                match (
+                    expected.trim().parse::<i128>(),
+                    actual.trim().parse::<i128>(),
+                ) {
+                    (Ok(expected), Ok(actual)) => expected == actual,
+                    _ => match (expected.trim().parse::<f64>(), actual.trim().parse::<f64>()) {
+                        (Ok(expected), Ok(actual)) => {
+                            expected == actual || (expected.is_nan() && actual.is_nan())
+                        }
+                        _ => false,
+                    },
+                }
+            }
+            Self::Boolean => match (parse_bool(expected), parse_bool(actual)) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => false,
+            },
+            Self::Date => match (parse_date(expected), parse_date(actual)) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => false,
+            },
+            Self::Json => match json::compare(expected, actual) {
+                JsonComparison::Same => true,
+                JsonComparison::Different(_) | JsonComparison::NotJson => false,
+            },
+        }
+    }
+
+    /// The value of a cell written the same way as every other cell it matches, or None if it can't be read as this
+    /// type and is compared as text.
+    pub fn canonical(self, cell: &str) -> Option<String> {
+        match self {
+            Self::Text => None,
+            Self::Integer | Self::Decimal => canonical_number(cell),
+            Self::Boolean => parse_bool(cell).map(|value| value.to_string()),
+            Self::Date => parse_date(cell).map(|value| value.to_string()),
+            Self::Json => json::canonical(cell),
+        }
+    }
+
+    /// Whether a cell can be read as this type.
+    pub fn parses(self, cell: &str) -> bool {
+        match self {
+            Self::Text => true,
+            Self::Integer => cell.trim().parse::<i128>().is_ok(),
+            Self::Decimal => cell.trim().parse::<f64>().is_ok(),
+            Self::Boolean => parse_bool(cell).is_some(),
+            Self::Date => parse_date(cell).is_some(),
+            Self::Json => serde_json::from_str::<serde_json::Value>(cell).is_ok(),
+        }
+    }
+
+    /// Guesses the type of a column from some of its values, leaving out empty ones.  Booleans have to be written
+    /// as words, since a column of 1s and 0s is more likely to hold numbers.  A column with a number written with a
+    /// leading zero holds codes, so it isn't read as numbers at all, or `007` would match `7`.  Decimals have to fit in a decimal without
+    /// losing precision, since long codes of digits are more likely to be text, and a column needs at least one
+    /// written out in digits, rather than as `inf` or `NaN`.
+    pub fn infer<'a>(values: impl Iterator<Item = &'a str>) -> Option<ColumnType> {
+        let mut candidates = vec![Self::Integer, Self::Decimal, Self::Boolean, Self::Date];
+        let mut any_values = false;
+        let mut any_decimals = false;
+        for value in values.filter(|value| !value.trim().is_empty()) {
+            any_values = true;
+            any_decimals |= is_exact_decimal(value);
+            let leading_zero = has_leading_zero(value);
+            candidates.retain(|candidate| match candidate {
+                Self::Boolean => {
+                    matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "false")
+                }
+                Self::Integer => !leading_zero && candidate.parses(value),
+                Self::Decimal => !leading_zero && (is_exact_decimal(value) || is_non_finite(value)),
+                _ => candidate.parses(value),
+            });
+            if candidates.is_empty() {
+                return Some(Self::Text);
+            }
+        }
+        if !any_decimals {
+            candidates.retain(|candidate| *candidate != Self::Decimal);
+        }
+        if any_values {
+            candidates.first().cloned()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    IO(io::Error),
+    Csv(csv::Error),
+    Json {
+        line: usize,
+        error: serde_json::Error,
+    },
+    Parquet(::parquet::errors::ParquetError),
+    Spreadsheet(calamine::Error),
+    MissingSheet(String),
+    MissingLayout,
+    Layout(String),
+    Sqlite(rusqlite::Error),
+    MissingQuery,
+    /// The thread running a SQLite query stopped before the query was done.
+    QueryStopped,
+    UnsupportedSkip(Format),
+    /// A column given on the command line that neither file has.
+    MissingColumn(String),
+}
+
+impl SourceError {
+    /// The underlying IO error, if reading failed before any content could be parsed.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Self::IO(error) => Some(error),
+            Self::Csv(error) => match error.kind() {
+                csv::ErrorKind::Io(error) => Some(error),
+                _ => None,
+            },
+            Self::Json { line: _, error: _ } => None,
+            Self::Parquet(::parquet::errors::ParquetError::External(error)) => error.downcast_ref(),
+            Self::Parquet(_) => None,
+            Self::Spreadsheet(calamine::Error::Io(error)) => Some(error),
+            Self::Spreadsheet(_) => None,
+            Self::MissingSheet(_) => None,
+            Self::MissingLayout => None,
+            Self::Layout(_) => None,
+            Self::Sqlite(_) => None,
+            Self::MissingQuery => None,
+            Self::UnsupportedSkip(_) => None,
+            Self::MissingColumn(_) => None,
+            Self::QueryStopped => None,
+        }
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IO(error) => write!(f, "{}", error),
+            Self::Csv(error) => write!(f, "{}", error),
+            Self::Json { line, error } => write!(f, "line {}: {}", line, error),
+            Self::Parquet(error) => write!(f, "{}", error),
+            Self::Spreadsheet(error) => write!(f, "{}", error),
+            Self::MissingSheet(sheet) => write!(f, "the workbook has no sheet {}", sheet),
+            Self::MissingLayout => write!(f, "fixed-width files need a layout"),
+            Self::Layout(message) => write!(f, "invalid layout: {}", message),
+            Self::Sqlite(error) => write!(f, "{}", error),
+            Self::MissingQuery => write!(f, "SQLite databases need a table or query"),
+            Self::QueryStopped => write!(f, "the query stopped before it was done"),
+            Self::UnsupportedSkip(format) => {
+                write!(f, "lines can't be skipped in {} files", format)
+            }
+            Self::MissingColumn(column) => write!(f, "there is no column named {}", column),
+        }
+    }
+}
+
+impl From<io::Error> for SourceError {
+    fn from(error: io::Error) -> Self {
+        SourceError::IO(error)
+    }
+}
+
+impl From<csv::Error> for SourceError {
+    fn from(error: csv::Error) -> Self {
+        SourceError::Csv(error)
+    }
+}
+
+impl From<::parquet::errors::ParquetError> for SourceError {
+    fn from(error: ::parquet::errors::ParquetError) -> Self {
+        SourceError::Parquet(error)
+    }
+}
+
+impl From<calamine::Error> for SourceError {
+    fn from(error: calamine::Error) -> Self {
+        SourceError::Spreadsheet(error)
+    }
+}
+
+impl From<rusqlite::Error> for SourceError {
+    fn from(error: rusqlite::Error) -> Self {
+        SourceError::Sqlite(error)
+    }
+}
+
+/// A file that can be read as a series of records, regardless of how it is stored on disk.
+pub trait Source: Iterator<Item = Result<csv::StringRecord, SourceError>> {
+    /// The names of the columns, in the order the cells of each record are laid out.
+    fn headers(&self) -> &csv::StringRecord;
+
+    /// The type of the values in the given column, if the source knows it.
+    fn column_type(&self, _column: usize) -> Option<ColumnType> {
+        None
+    }
+
+    /// The name of the sheet being read, for workbooks.
+    fn sheet_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Lays out the cells of each record in the same order as the given headers, for sources with no inherent column
+    /// order.  Columns that aren't in the given headers come after the ones that are.
+    fn align_to(&mut self, _headers: &csv::StringRecord) {}
+
+    /// The table of named columns that records are laid out with, for sources whose columns come from names.
+    fn named_columns(&self) -> Option<SharedColumns> {
+        None
+    }
+
+    /// Lays out records with another source's table of named columns from now on, so that columns first seen partway
+    /// through either source line up with each other.  This is done after aligning the sources.
+    fn share_named_columns(&mut self, _columns: SharedColumns) {}
+
+    /// The records at the end of the source that were held back to be compared on their own.  These are only known
+    /// once every other record has been read.
+    fn trailer(&self) -> &[csv::StringRecord] {
+        &[]
+    }
+}
+
+/// How to read a file, as given on the command line.
+pub struct SourceOptions {
+    pub format: Format,
+    pub delimiter: Delimiter,
+    pub sheet: Option<String>,
+    pub layout: Option<PathBuf>,
+    pub query: Option<SqliteQuery>,
+    pub skip: SkipOptions,
+    pub trailer_lines: usize,
+}
+
+pub fn get_source<P: AsRef<Path>>(
+    filepath: P,
+    options: &SourceOptions,
+) -> Result<Box<dyn Source>, SourceError> {
+    let skip = &options.skip;
+    let source: Box<dyn Source> = match options.format {
+        Format::Csv => Box::new(DelimitedSource::open(filepath, options.delimiter, skip)?),
+        Format::Jsonl => Box::new(JsonLinesSource::open(filepath, skip)?),
+        Format::FixedWidth => match &options.layout {
+            Some(layout) => Box::new(FixedWidthSource::open(filepath, layout, skip)?),
+            None => return Err(SourceError::MissingLayout),
+        },
+        // Workbooks have rows rather than lines, so only the leading ones can be skipped.
+        Format::Xlsx if skip.skips_matching_lines() => {
+            return Err(SourceError::UnsupportedSkip(options.format))
+        }
+        Format::Xlsx => Box::new(SpreadsheetSource::open(
+            filepath,
+            options.sheet.as_deref(),
+            skip.leading_lines,
+        )?),
+        Format::Parquet | Format::Sqlite
+            if skip.leading_lines > 0 || skip.skips_matching_lines() =>
+        {
+            return Err(SourceError::UnsupportedSkip(options.format))
+        }
+        Format::Parquet => Box::new(ParquetSource::open(filepath)?),
+        Format::Sqlite => match &options.query {
+            Some(query) => Box::new(SqliteSource::open(filepath, query.clone())?),
+            None => return Err(SourceError::MissingQuery),
+        },
+    };
+
+    if options.trailer_lines > 0 {
+        Ok(Box::new(TrailerSource::new(source, options.trailer_lines)))
+    } else {
+        Ok(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(values: &[&str]) -> Option<ColumnType> {
+        ColumnType::infer(values.iter().copied())
+    }
+
+    #[test]
+    fn infers_column_types() {
+        assert_eq!(infer(&[]), None);
+        assert_eq!(infer(&["", " "]), None);
+        assert_eq!(infer(&["1", "-20", "0"]), Some(ColumnType::Integer));
+        assert_eq!(infer(&["1", "2.5", "NaN"]), Some(ColumnType::Decimal));
+        assert_eq!(infer(&["true", "FALSE"]), Some(ColumnType::Boolean));
+        assert_eq!(infer(&["2024-01-31"]), Some(ColumnType::Date));
+        assert_eq!(infer(&["1", "two"]), Some(ColumnType::Text));
+        assert_eq!(infer(&["1234567890.123456789"]), Some(ColumnType::Text));
+    }
+
+    #[test]
+    fn infers_text_for_numbers_with_leading_zeros() {
+        assert_eq!(infer(&["1234", "01234"]), Some(ColumnType::Text));
+        assert_eq!(infer(&["00042"]), Some(ColumnType::Text));
+        assert_eq!(infer(&["-07", "5"]), Some(ColumnType::Text));
+        assert_eq!(infer(&["01.5", "2.5"]), Some(ColumnType::Text));
+        assert_eq!(infer(&["0", "10", "-0"]), Some(ColumnType::Integer));
+        assert_eq!(infer(&["0.5", "-0.25"]), Some(ColumnType::Decimal));
+    }
+}
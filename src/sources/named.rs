@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The names of the columns of sources whose records are made of named values, with the index each name is laid out
+/// at.  Both sides of a comparison can share one table, so a name first seen partway through either file is laid out
+/// at the same index in both.
+#[derive(Debug, Default)]
+pub struct ColumnTable {
+    headers: csv::StringRecord,
+    indexes: HashMap<String, usize>,
+}
+
+impl ColumnTable {
+    fn index(&mut self, name: &str) -> usize {
+        if let Some(index) = self.indexes.get(name) {
+            return *index;
+        }
+        let index = self.headers.len();
+        self.headers.push_field(name);
+        self.indexes.insert(name.to_string(), index);
+        index
+    }
+}
+
+pub type SharedColumns = Rc<RefCell<ColumnTable>>;
+
+/// Lays out named values as the cells of records, in the order their names were first seen.
+#[derive(Debug, Default)]
+pub struct NamedColumns {
+    table: SharedColumns,
+    /// The names in the table as of the last record laid out, kept here so they can be borrowed.
+    headers: csv::StringRecord,
+    /// How many columns every record has, even if it has no value for some of them.
+    num_aligned_columns: usize,
+}
+
+impl NamedColumns {
+    /// Starts with the given columns, which every record is laid out with.
+    pub fn new<'a>(names: impl Iterator<Item = &'a str>) -> Self {
+        let mut columns = NamedColumns::default();
+        for name in names {
+            columns.table.borrow_mut().index(name);
+        }
+        columns.sync();
+        columns.num_aligned_columns = columns.headers.len();
+        columns
+    }
+
+    pub fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    /// Lays out the cells of a record, adding a column for each name that hasn't been seen before.
+    pub fn record_from(&mut self, cells: Vec<(String, String)>) -> csv::StringRecord {
+        let mut record = vec![String::new(); self.num_aligned_columns];
+        {
+            let mut table = self.table.borrow_mut();
+            for (name, value) in cells {
+                let index = table.index(&name);
+                if index >= record.len() {
+                    record.resize(index + 1, String::new());
+                }
+                record[index] = value;
+            }
+        }
+        self.sync();
+        csv::StringRecord::from(record)
+    }
+
+    /// Puts the given columns first, in their order, followed by any others already seen.
+    pub fn align_to(&mut self, headers: &csv::StringRecord) {
+        let mut table = ColumnTable::default();
+        for name in headers.iter().chain(self.headers.iter()) {
+            table.index(name);
+        }
+        self.table = Rc::new(RefCell::new(table));
+        self.headers = csv::StringRecord::new();
+        self.sync();
+        self.num_aligned_columns = headers.len();
+    }
+
+    pub fn shared(&self) -> SharedColumns {
+        Rc::clone(&self.table)
+    }
+
+    /// Lays out records with another source's table from now on, adding the columns seen so far to it.
+    pub fn share(&mut self, table: SharedColumns) {
+        for name in self.headers.iter() {
+            table.borrow_mut().index(name);
+        }
+        self.table = table;
+        self.headers = csv::StringRecord::new();
+        self.sync();
+    }
+
+    fn sync(&mut self) {
+        let table = self.table.borrow();
+        for name in table.headers.iter().skip(self.headers.len()) {
+            self.headers.push_field(name);
+        }
+    }
+}
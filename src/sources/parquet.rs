@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use ::parquet::basic::{ConvertedType, LogicalType, Repetition, Type as PhysicalType};
+use ::parquet::file::reader::{FileReader, SerializedFileReader};
+use ::parquet::record::reader::RowIter;
+use ::parquet::record::{Field, Row};
+use ::parquet::schema::types::Type as SchemaType;
+
+use super::{ColumnType, NamedColumns, SharedColumns, Source, SourceError};
+
+/// Reads the rows of a Parquet file.  Columns are named after their path in the schema, with nested fields joined by
+/// dots, and keep the type the schema gives them so that, say, `1` and `1.0` compare as the same number.  Lists and
+/// maps are kept whole in one column, as JSON.
+pub struct ParquetSource {
+    rows: RowIter<'static>,
+    columns: NamedColumns,
+    column_types: HashMap<String, ColumnType>,
+}
+
+fn column_type(column: &SchemaType) -> ColumnType {
+    if is_list_or_map(column) {
+        return ColumnType::Json;
+    }
+    let info = column.get_basic_info();
+    match info.logical_type() {
+        Some(LogicalType::Date)
+        | Some(LogicalType::Time { .. })
+        | Some(LogicalType::Timestamp { .. }) => return ColumnType::Text,
+        Some(LogicalType::Decimal { .. }) => return ColumnType::Decimal,
+        _ => {}
+    }
+    match info.converted_type() {
+        ConvertedType::DATE
+        | ConvertedType::TIME_MILLIS
+        | ConvertedType::TIME_MICROS
+        | ConvertedType::TIMESTAMP_MILLIS
+        | ConvertedType::TIMESTAMP_MICROS => return ColumnType::Text,
+        ConvertedType::DECIMAL => return ColumnType::Decimal,
+        _ => {}
+    }
+    match column.get_physical_type() {
+        PhysicalType::BOOLEAN => ColumnType::Boolean,
+        PhysicalType::INT32 | PhysicalType::INT64 | PhysicalType::INT96 => ColumnType::Integer,
+        PhysicalType::FLOAT | PhysicalType::DOUBLE => ColumnType::Decimal,
+        PhysicalType::BYTE_ARRAY | PhysicalType::FIXED_LEN_BYTE_ARRAY => ColumnType::Text,
+    }
+}
+
+/// Whether a field is read as a list or a map, which the rows hold as a single value rather than as nested fields.
+fn is_list_or_map(field: &SchemaType) -> bool {
+    let info = field.get_basic_info();
+    (info.has_repetition() && info.repetition() == Repetition::REPEATED)
+        || matches!(
+            info.logical_type(),
+            Some(LogicalType::List) | Some(LogicalType::Map)
+        )
+        || matches!(
+            info.converted_type(),
+            ConvertedType::LIST | ConvertedType::MAP | ConvertedType::MAP_KEY_VALUE
+        )
+}
+
+/// Lists the columns of a group as `flatten_into` lays out the cells of its rows, with their types.
+fn columns_of(prefix: &str, group: &SchemaType, columns: &mut Vec<(String, ColumnType)>) {
+    for field in group.get_fields() {
+        let path = if prefix.is_empty() {
+            field.name().to_string()
+        } else {
+            format!("{}.{}", prefix, field.name())
+        };
+        if field.is_group() && !is_list_or_map(field) {
+            columns_of(&path, field, columns);
+        } else {
+            columns.push((path, column_type(field)));
+        }
+    }
+}
+
+fn flatten_into(prefix: &str, row: &Row, cells: &mut Vec<(String, String)>) {
+    for (name, field) in row.get_column_iter() {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match field {
+            Field::Group(nested) => flatten_into(&path, nested, cells),
+            Field::Null => cells.push((path, String::new())),
+            Field::Str(string) => cells.push((path, string.clone())),
+            Field::ListInternal(_) | Field::MapInternal(_) => {
+                cells.push((path, field.to_json_value().to_string()))
+            }
+            other => cells.push((path, other.to_string())),
+        }
+    }
+}
+
+impl ParquetSource {
+    pub fn open<P: AsRef<Path>>(filepath: P) -> Result<Self, SourceError> {
+        let reader = SerializedFileReader::new(File::open(filepath)?)?;
+        let schema = reader.metadata().file_metadata().schema_descr_ptr();
+
+        let mut columns = vec![];
+        columns_of("", schema.root_schema(), &mut columns);
+        Ok(ParquetSource {
+            rows: reader.into_iter(),
+            columns: NamedColumns::new(columns.iter().map(|(name, _)| name.as_str())),
+            column_types: columns.into_iter().collect(),
+        })
+    }
+
+    fn record_from(&mut self, row: &Row) -> csv::StringRecord {
+        let mut flattened = vec![];
+        flatten_into("", row, &mut flattened);
+
+        self.columns.record_from(flattened)
+    }
+}
+
+impl Iterator for ParquetSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.next()? {
+            Ok(row) => Some(Ok(self.record_from(&row))),
+            Err(error) => Some(Err(error.into())),
+        }
+    }
+}
+
+impl Source for ParquetSource {
+    fn headers(&self) -> &csv::StringRecord {
+        self.columns.headers()
+    }
+
+    fn column_type(&self, column: usize) -> Option<ColumnType> {
+        self.columns
+            .headers()
+            .get(column)
+            .and_then(|name| self.column_types.get(name))
+            .cloned()
+    }
+
+    fn align_to(&mut self, headers: &csv::StringRecord) {
+        self.columns.align_to(headers)
+    }
+
+    fn named_columns(&self) -> Option<SharedColumns> {
+        Some(self.columns.shared())
+    }
+
+    fn share_named_columns(&mut self, columns: SharedColumns) {
+        self.columns.share(columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ::parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type};
+    use ::parquet::file::properties::WriterProperties;
+    use ::parquet::file::writer::SerializedFileWriter;
+    use ::parquet::schema::parser::parse_message_type;
+
+    use super::*;
+
+    const SCHEMA: &str = "
+        message test {
+            required int32 id;
+            optional group tags (LIST) {
+                repeated group list {
+                    optional int32 element;
+                }
+            }
+            optional group counts (MAP) {
+                repeated group key_value {
+                    required binary key (UTF8);
+                    optional int32 value;
+                }
+            }
+            optional group point {
+                optional double x;
+            }
+        }";
+
+    /// Writes one row: id 1, tags [1, 2], counts {"a": 3} and a point whose x isn't a number.
+    fn write_file(filepath: &Path) {
+        let schema = Arc::new(parse_message_type(SCHEMA).unwrap());
+        let file = File::create(filepath).unwrap();
+        let mut writer =
+            SerializedFileWriter::new(file, schema, Arc::new(WriterProperties::default())).unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column().unwrap() {
+            match index {
+                0 => column
+                    .typed::<Int32Type>()
+                    .write_batch(&[1], None, None)
+                    .unwrap(),
+                1 => column
+                    .typed::<Int32Type>()
+                    .write_batch(&[1, 2], Some(&[3, 3]), Some(&[0, 1]))
+                    .unwrap(),
+                2 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&[ByteArray::from("a")], Some(&[2]), Some(&[0]))
+                    .unwrap(),
+                3 => column
+                    .typed::<Int32Type>()
+                    .write_batch(&[3], Some(&[3]), Some(&[0]))
+                    .unwrap(),
+                _ => column
+                    .typed::<DoubleType>()
+                    .write_batch(&[f64::NAN], Some(&[2]), None)
+                    .unwrap(),
+            };
+            column.close().unwrap();
+            index += 1;
+        }
+        row_group.close().unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn keeps_lists_and_maps_in_one_column() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let filepath = temp_dir.path().join("test.parquet");
+        write_file(&filepath);
+
+        let mut source = ParquetSource::open(&filepath).unwrap();
+        assert_eq!(
+            source.headers(),
+            &csv::StringRecord::from(vec!["id", "tags", "counts", "point.x"])
+        );
+        let types: Vec<Option<ColumnType>> =
+            (0..4).map(|column| source.column_type(column)).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(ColumnType::Integer),
+                Some(ColumnType::Json),
+                Some(ColumnType::Json),
+                Some(ColumnType::Decimal)
+            ]
+        );
+
+        let record = source.next().unwrap().unwrap();
+        assert_eq!(record.len(), 4);
+        assert_eq!(&record[0], "1");
+        assert!(ColumnType::Json.cells_match(&record[1], "[1, 2]"));
+        assert!(ColumnType::Json.cells_match(&record[2], r#"{"a": 3}"#));
+        assert!(ColumnType::Decimal.cells_match(&record[3], "NaN"));
+        assert_eq!(source.headers().len(), 4);
+    }
+}
@@ -0,0 +1,138 @@
+use super::{ColumnType, Source, SourceError};
+
+/// Every record of a source read into memory, so that the source can be compared against several other files while
+/// only being read once.
+pub struct RecordedSource {
+    headers: csv::StringRecord,
+    column_types: Vec<Option<ColumnType>>,
+    sheet_name: Option<String>,
+    records: Vec<csv::StringRecord>,
+    trailer: Vec<csv::StringRecord>,
+}
+
+impl RecordedSource {
+    pub fn record(source: &mut dyn Source) -> Result<Self, SourceError> {
+        let mut records = (&mut *source).collect::<Result<Vec<_>, _>>()?;
+        let headers = source.headers().clone();
+        // Records read before a column was first seen don't have a cell for it yet.
+        if source.named_columns().is_some() {
+            for record in &mut records {
+                while record.len() < headers.len() {
+                    record.push_field("");
+                }
+            }
+        }
+        Ok(RecordedSource {
+            column_types: (0..headers.len())
+                .map(|column| source.column_type(column))
+                .collect(),
+            headers,
+            sheet_name: source.sheet_name().map(|sheet| sheet.to_string()),
+            records,
+            trailer: source.trailer().to_vec(),
+        })
+    }
+
+    /// Reads the recorded records again from the start.
+    pub fn replay(&self) -> Replay<'_> {
+        Replay {
+            recorded: self,
+            headers: self.headers.clone(),
+            columns: None,
+            next_record: 0,
+            trailer: self.trailer.clone(),
+        }
+    }
+}
+
+pub struct Replay<'a> {
+    recorded: &'a RecordedSource,
+    headers: csv::StringRecord,
+    /// Where each column comes from in the recorded records, once aligned to another file's columns.
+    columns: Option<Vec<Option<usize>>>,
+    next_record: usize,
+    trailer: Vec<csv::StringRecord>,
+}
+
+impl<'a> Replay<'a> {
+    fn arrange(&self, record: &csv::StringRecord) -> csv::StringRecord {
+        match &self.columns {
+            None => record.clone(),
+            Some(columns) => {
+                let mut arranged: csv::StringRecord = columns
+                    .iter()
+                    .map(|column| column.and_then(|column| record.get(column)).unwrap_or(""))
+                    .collect();
+                arranged.set_position(record.position().cloned());
+                arranged
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Replay<'a> {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.recorded.records.get(self.next_record)?;
+        self.next_record += 1;
+        Some(Ok(self.arrange(record)))
+    }
+}
+
+impl<'a> Source for Replay<'a> {
+    fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    fn column_type(&self, column: usize) -> Option<ColumnType> {
+        let column = match &self.columns {
+            None => Some(column),
+            Some(columns) => columns.get(column).cloned().flatten(),
+        };
+        column.and_then(|column| self.recorded.column_types.get(column).cloned().flatten())
+    }
+
+    fn sheet_name(&self) -> Option<&str> {
+        self.recorded.sheet_name.as_deref()
+    }
+
+    /// Lines the columns up by name, the same way sources that match columns by name do, with any recorded columns
+    /// the other file doesn't have coming last.
+    fn align_to(&mut self, headers: &csv::StringRecord) {
+        let recorded_headers = &self.recorded.headers;
+        let mut columns: Vec<Option<usize>> = headers
+            .iter()
+            .map(|name| {
+                recorded_headers
+                    .iter()
+                    .position(|recorded| recorded == name)
+            })
+            .collect();
+        columns.extend(
+            (0..recorded_headers.len())
+                .filter(|column| !columns.contains(&Some(*column)))
+                .map(Some)
+                .collect::<Vec<_>>(),
+        );
+        self.headers = headers
+            .iter()
+            .chain(
+                recorded_headers
+                    .iter()
+                    .filter(|recorded| !headers.iter().any(|name| name == *recorded)),
+            )
+            .collect();
+        self.columns = Some(columns);
+        self.trailer = self
+            .recorded
+            .trailer
+            .iter()
+            .map(|record| self.arrange(record))
+            .collect();
+    }
+
+    fn trailer(&self) -> &[csv::StringRecord] {
+        &self.trailer
+    }
+}
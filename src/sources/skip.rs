@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use regex::bytes::Regex;
+
+/// Which lines of a text file to leave out before it's read.
+#[derive(Debug, Clone, Default)]
+pub struct SkipOptions {
+    /// How many lines at the start of the file come before the real content, like a title block.
+    pub leading_lines: usize,
+    /// Lines starting with this are left out wherever they are, like `#` comments.
+    pub prefix: Option<String>,
+    /// Lines matching this are left out wherever they are.
+    pub pattern: Option<Regex>,
+}
+
+impl SkipOptions {
+    /// Whether any lines are skipped other than the leading ones.
+    pub fn skips_matching_lines(&self) -> bool {
+        self.prefix.is_some() || self.pattern.is_some()
+    }
+
+    fn skips(&self, line_number: usize, line: &[u8]) -> bool {
+        line_number <= self.leading_lines
+            || self
+                .prefix
+                .as_ref()
+                .map(|prefix| line.starts_with(prefix.as_bytes()))
+                .unwrap_or(false)
+            || self
+                .pattern
+                .as_ref()
+                .map(|pattern| pattern.is_match(line))
+                .unwrap_or(false)
+    }
+}
+
+/// Reads a text file with the skipped lines blanked out.  The line endings are left in place, so the lines that remain
+/// keep their line numbers, and the readers for each format pass over blank lines.
+pub struct SkippedLines<R> {
+    inner: R,
+    options: SkipOptions,
+    line_number: usize,
+    line: Vec<u8>,
+    position: usize,
+    /// For each skipped line, the number after it and how many bytes had been blanked out by the end of it.
+    removed_bytes: Vec<(u64, u64)>,
+    /// The length of each line that was read as blank, whether it was skipped or blank to begin with.
+    blank_lines: HashMap<u64, u64>,
+    /// The first and last number of each run of lines that end with `\r\n`.
+    crlf_lines: Vec<(u64, u64)>,
+}
+
+impl SkippedLines<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(filepath: P, options: &SkipOptions) -> io::Result<Self> {
+        Ok(SkippedLines {
+            inner: BufReader::new(File::open(filepath)?),
+            options: options.clone(),
+            line_number: 0,
+            line: vec![],
+            position: 0,
+            removed_bytes: vec![],
+            blank_lines: HashMap::new(),
+            crlf_lines: vec![],
+        })
+    }
+}
+
+impl<R> SkippedLines<R> {
+    /// Converts the position of a record in what was read back to where the record starts in the original file.
+    ///
+    /// The CSV reader gives records the position they started being read from, which is before any blank lines ahead
+    /// of them, so those are stepped over first.
+    pub fn original_position(&self, position: &csv::Position) -> csv::Position {
+        let mut line_number = position.line();
+        let mut byte = position.byte();
+        while let Some(len) = self.blank_lines.get(&line_number) {
+            line_number += 1;
+            byte += len;
+        }
+
+        let num_before = self
+            .removed_bytes
+            .partition_point(|(after_line_number, _)| *after_line_number <= line_number);
+        let removed = match num_before {
+            0 => 0,
+            num_before => self.removed_bytes[num_before - 1].1,
+        };
+        let mut original = position.clone();
+        original.set_line(line_number).set_byte(byte + removed);
+        original
+    }
+
+    /// Like `original_position`, for the position of a record from a CSV reader.
+    ///
+    /// The CSV reader ends a record at the `\r` of a `\r\n`, so the records after it are given the position of the
+    /// `\n` that's left, which is still on the line before.
+    pub fn original_record_position(&self, position: &csv::Position) -> csv::Position {
+        if position.byte() == 0 || !self.ends_with_crlf(position.line()) {
+            return self.original_position(position);
+        }
+        let mut next_line = position.clone();
+        next_line
+            .set_line(position.line() + 1)
+            .set_byte(position.byte() + 1);
+        self.original_position(&next_line)
+    }
+
+    fn ends_with_crlf(&self, line_number: u64) -> bool {
+        let num_before = self
+            .crlf_lines
+            .partition_point(|(first, _)| *first <= line_number);
+        num_before > 0 && line_number <= self.crlf_lines[num_before - 1].1
+    }
+}
+
+impl<R: BufRead> Read for SkippedLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.line.len() {
+            self.line.clear();
+            self.position = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(0);
+            }
+            self.line_number += 1;
+
+            let content_len = self.line.len()
+                - self
+                    .line
+                    .iter()
+                    .rev()
+                    .take_while(|byte| **byte == b'\n' || **byte == b'\r')
+                    .count();
+            if self.line[content_len..].starts_with(b"\r\n") {
+                let line_number = self.line_number as u64;
+                match self.crlf_lines.last_mut() {
+                    Some((_, last)) if *last + 1 == line_number => *last = line_number,
+                    _ => self.crlf_lines.push((line_number, line_number)),
+                }
+            }
+            if self
+                .options
+                .skips(self.line_number, &self.line[..content_len])
+            {
+                self.line.drain(..content_len);
+                let removed = self
+                    .removed_bytes
+                    .last()
+                    .map(|(_, removed)| *removed)
+                    .unwrap_or(0);
+                self.removed_bytes
+                    .push((self.line_number as u64 + 1, removed + content_len as u64));
+            }
+            if self
+                .line
+                .iter()
+                .all(|byte| *byte == b'\n' || *byte == b'\r')
+            {
+                self.blank_lines
+                    .insert(self.line_number as u64, self.line.len() as u64);
+            }
+        }
+
+        let len = (&self.line[self.position..]).read(buf)?;
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// The cells of each record after the header, along with the line and byte it starts at in the original file.
+    fn records(contents: &str, options: SkipOptions) -> Vec<(Vec<String>, u64, u64)> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(SkippedLines::open(file.path(), &options).unwrap());
+        let mut records = vec![];
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record).unwrap() {
+            let position = reader
+                .get_ref()
+                .original_record_position(record.position().unwrap());
+            records.push((
+                record.iter().map(String::from).collect(),
+                position.line(),
+                position.byte(),
+            ));
+        }
+        records
+    }
+
+    fn cells(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn skips_leading_lines_before_the_header() {
+        let options = SkipOptions {
+            leading_lines: 2,
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            records("Title\nGenerated\nid,name\n1,a\n2,b\n", options),
+            vec![(cells(&["1", "a"]), 4, 24), (cells(&["2", "b"]), 5, 28)]
+        );
+    }
+
+    #[test]
+    fn skips_prefixed_and_matching_lines_in_the_middle() {
+        let options = SkipOptions {
+            prefix: Some("#".to_string()),
+            pattern: Some(Regex::new("^SKIP").unwrap()),
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            records("id,name\n# note\n1,a\nSKIP me\n\n2,b\n", options),
+            vec![(cells(&["1", "a"]), 3, 15), (cells(&["2", "b"]), 6, 28)]
+        );
+    }
+
+    #[test]
+    fn counts_both_bytes_of_crlf_line_endings() {
+        let options = SkipOptions {
+            leading_lines: 1,
+            prefix: Some("#".to_string()),
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            records("Title\r\nid,name\r\n1,a\r\n# c\r\n2,b\r\n", options),
+            vec![(cells(&["1", "a"]), 3, 16), (cells(&["2", "b"]), 5, 26)]
+        );
+    }
+
+    #[test]
+    fn finds_quoted_multi_line_records_after_skipped_lines() {
+        let options = SkipOptions {
+            prefix: Some("#".to_string()),
+            ..SkipOptions::default()
+        };
+        assert_eq!(
+            records("# c\nid,note\n# d\n1,\"two\nlines\"\n# e\n2,x\n", options),
+            vec![
+                (cells(&["1", "two\nlines"]), 4, 16),
+                (cells(&["2", "x"]), 7, 34)
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use calamine::{open_workbook_auto, Data, Reader};
+use chrono::NaiveTime;
+
+use super::{Source, SourceError};
+
+/// Reads a sheet of a workbook.  The first row of the sheet is the header, and every cell is converted to the text a
+/// CSV export would have, so that dates read as `2020-01-31` and whole numbers read as `3` rather than `3.0`.
+pub struct SpreadsheetSource {
+    sheet_name: String,
+    headers: csv::StringRecord,
+    records: std::vec::IntoIter<csv::StringRecord>,
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(string) | Data::DateTimeIso(string) | Data::DurationIso(string) => {
+            string.clone()
+        }
+        Data::Int(int) => int.to_string(),
+        // Excel stores every number as a float, so whole numbers lose their decimal point.
+        Data::Float(float) if float.fract() == 0.0 && float.abs() < 1e15 => {
+            format!("{}", *float as i64)
+        }
+        Data::Float(float) => float.to_string(),
+        Data::Bool(boolean) => boolean.to_string(),
+        Data::DateTime(date_time) if date_time.is_duration() => match date_time.as_duration() {
+            Some(duration) => format!(
+                "{}:{:02}:{:02}",
+                duration.num_hours(),
+                duration.num_minutes() % 60,
+                duration.num_seconds() % 60
+            ),
+            None => date_time.to_string(),
+        },
+        Data::DateTime(date_time) => match date_time.as_datetime() {
+            Some(date_time) if date_time.time() == NaiveTime::MIN => {
+                date_time.format("%Y-%m-%d").to_string()
+            }
+            Some(date_time) => date_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => date_time.to_string(),
+        },
+        Data::Error(error) => error.to_string(),
+    }
+}
+
+fn record_from(row: &[Data], leading_columns: usize) -> csv::StringRecord {
+    // Empty cells at the end of a row are only there because the sheet is a rectangle, so they're dropped.
+    let num_cells = row
+        .iter()
+        .rposition(|cell| *cell != Data::Empty)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    let mut record = csv::StringRecord::new();
+    for _ in 0..leading_columns {
+        record.push_field("");
+    }
+    for cell in &row[..num_cells] {
+        record.push_field(&cell_text(cell));
+    }
+    record
+}
+
+impl SpreadsheetSource {
+    /// Opens the given sheet of a workbook, either by name or by its position (starting from 1).  Without a sheet, the
+    /// first one is read.  The header comes after the given number of leading rows.
+    pub fn open<P: AsRef<Path>>(
+        filepath: P,
+        sheet: Option<&str>,
+        leading_rows: usize,
+    ) -> Result<Self, SourceError> {
+        let mut workbook = open_workbook_auto(filepath)?;
+        let sheet_names = workbook.sheet_names();
+        let sheet_name = match sheet {
+            None => sheet_names.first().cloned(),
+            Some(sheet) if sheet_names.iter().any(|name| name == sheet) => Some(sheet.to_string()),
+            Some(sheet) => sheet
+                .parse::<usize>()
+                .ok()
+                .and_then(|position| position.checked_sub(1))
+                .and_then(|index| sheet_names.get(index).cloned()),
+        }
+        .ok_or_else(|| SourceError::MissingSheet(sheet.unwrap_or_default().to_string()))?;
+
+        let range = workbook.worksheet_range(&sheet_name)?;
+        // Ranges start at the first cell that has anything in it, so columns before that are filled in to keep the
+        // column numbers the same as the sheet's.
+        let leading_columns = range
+            .start()
+            .map(|(_, column)| column as usize)
+            .unwrap_or(0);
+        let mut rows = range.rows().skip(leading_rows);
+        let headers = rows
+            .next()
+            .map(|row| record_from(row, leading_columns))
+            .unwrap_or_default();
+        let records: Vec<csv::StringRecord> =
+            rows.map(|row| record_from(row, leading_columns)).collect();
+
+        Ok(SpreadsheetSource {
+            sheet_name,
+            headers,
+            records: records.into_iter(),
+        })
+    }
+}
+
+impl Iterator for SpreadsheetSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(Ok)
+    }
+}
+
+impl Source for SpreadsheetSource {
+    fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    fn sheet_name(&self) -> Option<&str> {
+        Some(&self.sheet_name)
+    }
+}
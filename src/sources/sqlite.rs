@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+
+use super::{ColumnType, Source, SourceError};
+
+/// How many rows the query can get ahead of the comparison.
+const ROW_BUFFER_SIZE: usize = 1024;
+
+/// What to read out of a SQLite database.
+#[derive(Debug, Clone)]
+pub enum SqliteQuery {
+    Table(String),
+    Sql(String),
+}
+
+impl SqliteQuery {
+    fn sql(&self) -> String {
+        match self {
+            Self::Table(table) => format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")),
+            Self::Sql(sql) => sql.clone(),
+        }
+    }
+}
+
+/// What the query's thread sends: the columns of the result, then each of its rows.
+enum QueryMessage {
+    Columns(csv::StringRecord, Vec<Option<ColumnType>>),
+    Row(csv::StringRecord),
+}
+
+type QueryResult = Result<QueryMessage, rusqlite::Error>;
+
+/// Streams the rows of a table or query in a SQLite database.  The column names of the result become the headers, and
+/// columns of a table keep the type they were declared with.
+///
+/// Rows are read on a separate thread, since a statement can't outlive the connection it was prepared on.
+pub struct SqliteSource {
+    headers: csv::StringRecord,
+    column_types: Vec<Option<ColumnType>>,
+    rows: Receiver<QueryResult>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The type of a column declared with the given type, following the rules SQLite uses to decide how values are
+/// stored.  Columns declared without a type, or worked out by an expression, can hold anything.
+fn column_type(declared_type: Option<&str>) -> Option<ColumnType> {
+    let declared_type = declared_type?.to_ascii_uppercase();
+    let contains = |names: &[&str]| names.iter().any(|name| declared_type.contains(name));
+    if contains(&["INT"]) {
+        Some(ColumnType::Integer)
+    } else if contains(&["CHAR", "CLOB", "TEXT"]) {
+        Some(ColumnType::Text)
+    } else if declared_type.is_empty() || contains(&["BLOB"]) {
+        None
+    } else if contains(&["BOOL"]) {
+        Some(ColumnType::Boolean)
+    } else if contains(&["DATE", "TIME"]) {
+        Some(ColumnType::Date)
+    } else {
+        Some(ColumnType::Decimal)
+    }
+}
+
+fn cell_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(integer) => integer.to_string(),
+        // Written so that whole numbers still read as decimals, like 1.0 rather than 1.
+        ValueRef::Real(real) => format!("{:?}", real),
+        ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+/// Sends the columns of the query's result, followed by each of its rows.
+fn run_query(
+    filepath: PathBuf,
+    query: SqliteQuery,
+    sender: &SyncSender<QueryResult>,
+) -> Result<(), rusqlite::Error> {
+    let connection = Connection::open_with_flags(filepath, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(&query.sql())?;
+    let columns = statement.columns();
+    let headers: csv::StringRecord = columns.iter().map(|column| column.name()).collect();
+    let column_types = columns
+        .iter()
+        .map(|column| column_type(column.decl_type()))
+        .collect();
+    let num_columns = headers.len();
+    // If the receiving end has hung up, nobody is interested in the rows any more.
+    if sender
+        .send(Ok(QueryMessage::Columns(headers, column_types)))
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut record = csv::StringRecord::new();
+        for column in 0..num_columns {
+            record.push_field(&cell_text(row.get_ref(column)?));
+        }
+        if sender.send(Ok(QueryMessage::Row(record))).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+impl SqliteSource {
+    pub fn open<P: AsRef<Path>>(filepath: P, query: SqliteQuery) -> Result<Self, SourceError> {
+        // SQLite would happily create a database that doesn't exist, and its errors don't say why a file couldn't be
+        // opened, so the file is checked up front.
+        File::open(filepath.as_ref())?;
+
+        let filepath = filepath.as_ref().to_path_buf();
+        let (sender, receiver) = sync_channel(ROW_BUFFER_SIZE);
+        let thread = thread::spawn(move || {
+            if let Err(error) = run_query(filepath, query, &sender) {
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        // The thread sends the columns or an error before anything else, unless it stopped unexpectedly.
+        match receiver.recv() {
+            Ok(Ok(QueryMessage::Columns(headers, column_types))) => Ok(SqliteSource {
+                headers,
+                column_types,
+                rows: receiver,
+                thread: Some(thread),
+            }),
+            Ok(Err(error)) => Err(error.into()),
+            Ok(Ok(QueryMessage::Row(_))) | Err(_) => Err(SourceError::QueryStopped),
+        }
+    }
+}
+
+impl Iterator for SqliteSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.recv() {
+            Ok(Ok(QueryMessage::Row(record))) => Some(Ok(record)),
+            Ok(Err(error)) => Some(Err(error.into())),
+            Ok(Ok(QueryMessage::Columns(..))) => Some(Err(SourceError::QueryStopped)),
+            // The channel closes once the thread is done, which is only the end of the rows if the thread finished
+            // normally.
+            Err(_) => match self.thread.take()?.join() {
+                Ok(()) => None,
+                Err(_) => Some(Err(SourceError::QueryStopped)),
+            },
+        }
+    }
+}
+
+impl Source for SqliteSource {
+    fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    fn column_type(&self, column: usize) -> Option<ColumnType> {
+        self.column_types.get(column).cloned().flatten()
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+use super::{ColumnType, SharedColumns, Source, SourceError};
+
+/// Holds back the last records of another source as a trailer, like a `TOTAL,1234` line at the end of a file, so they
+/// can be compared on their own rather than as part of the body.
+pub struct TrailerSource {
+    inner: Box<dyn Source>,
+    trailer_len: usize,
+    held_back: VecDeque<csv::StringRecord>,
+    trailer: Vec<csv::StringRecord>,
+}
+
+impl TrailerSource {
+    pub fn new(inner: Box<dyn Source>, trailer_len: usize) -> Self {
+        TrailerSource {
+            inner,
+            trailer_len,
+            held_back: VecDeque::with_capacity(trailer_len + 1),
+            trailer: vec![],
+        }
+    }
+}
+
+impl Iterator for TrailerSource {
+    type Item = Result<csv::StringRecord, SourceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.held_back.len() <= self.trailer_len {
+            match self.inner.next() {
+                Some(Ok(record)) => self.held_back.push_back(record),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.trailer.extend(self.held_back.drain(..));
+                    return None;
+                }
+            }
+        }
+        self.held_back.pop_front().map(Ok)
+    }
+}
+
+impl Source for TrailerSource {
+    fn headers(&self) -> &csv::StringRecord {
+        self.inner.headers()
+    }
+
+    fn column_type(&self, column: usize) -> Option<ColumnType> {
+        self.inner.column_type(column)
+    }
+
+    fn sheet_name(&self) -> Option<&str> {
+        self.inner.sheet_name()
+    }
+
+    fn align_to(&mut self, headers: &csv::StringRecord) {
+        self.inner.align_to(headers)
+    }
+
+    fn named_columns(&self) -> Option<SharedColumns> {
+        self.inner.named_columns()
+    }
+
+    fn share_named_columns(&mut self, columns: SharedColumns) {
+        self.inner.share_named_columns(columns)
+    }
+
+    fn trailer(&self) -> &[csv::StringRecord] {
+        &self.trailer
+    }
+}
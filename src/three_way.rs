@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+
+use crate::cells::{self, CellComparison, ColumnComparison, Nulls};
+use crate::sources::{Source, SourceError};
+
+/// How a cell changed from the base file in each of the two files made from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellChange {
+    Unchanged,
+    ChangedInOurs,
+    ChangedInTheirs,
+    ChangedIdentically,
+    Conflicting,
+}
+
+impl CellChange {
+    /// Classifies a cell given whether each file's cell is the same as the base's, and whether the two files' cells
+    /// are the same as each other.
+    fn classify(
+        ours_same_as_base: bool,
+        theirs_same_as_base: bool,
+        ours_same_as_theirs: bool,
+    ) -> Self {
+        match (ours_same_as_base, theirs_same_as_base) {
+            (true, true) => Self::Unchanged,
+            (false, true) => Self::ChangedInOurs,
+            (true, false) => Self::ChangedInTheirs,
+            (false, false) if ours_same_as_theirs => Self::ChangedIdentically,
+            (false, false) => Self::Conflicting,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Unchanged => "Unchanged",
+            Self::ChangedInOurs => "Changed in ours",
+            Self::ChangedInTheirs => "Changed in theirs",
+            Self::ChangedIdentically => "Changed identically",
+            Self::Conflicting => "Conflicting",
+        }
+    }
+}
+
+/// A cell that changed from the base file, with its value in each file.  A cell in a row that a file doesn't have is
+/// shown as missing.
+#[derive(Debug, Serialize)]
+pub struct ChangedCell {
+    row: String,
+    column: String,
+    change: &'static str,
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+impl Display for ChangedCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}, column {} (base {}, ours {}, theirs {})",
+            self.change, self.row, self.column, self.base, self.ours, self.theirs
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChangeCounts {
+    pub unchanged: usize,
+    pub changed_in_ours: usize,
+    pub changed_in_theirs: usize,
+    pub changed_identically: usize,
+    pub conflicting: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreeWayComparison {
+    pub base_filename: String,
+    pub ours_filename: String,
+    pub theirs_filename: String,
+    pub counts: ChangeCounts,
+    pub conflicts: Vec<ChangedCell>,
+    pub changes: Vec<ChangedCell>,
+    /// Whether there were more cells changed without conflict than could be listed.  Conflicts are always listed.
+    pub found_max_cells: bool,
+}
+
+/// The rows of each file that line up with each other, along with how the rows are described.
+type RowSet<'a> = (String, [Option<&'a csv::StringRecord>; 3]);
+
+/// Compares two files made from a common base, cell by cell, to show which changes came from which file and which
+/// changes conflict.  The sources are given as base, ours and theirs.  Rows are lined up by position, or by key when
+/// key columns are given, with rows that share a key lined up in the order they appear in each file.  All three files
+/// are read into memory.
+pub fn compare(
+    sources: [&mut dyn Source; 3],
+    columns: &[ColumnComparison],
+    column_names: &[String],
+    key_columns: &[usize],
+    nulls: &Nulls,
+    max_cells: usize,
+) -> Result<ThreeWayComparison, SourceError> {
+    let [base, ours, theirs] = sources;
+    let files = [
+        base.collect::<Result<Vec<_>, _>>()?,
+        ours.collect::<Result<Vec<_>, _>>()?,
+        theirs.collect::<Result<Vec<_>, _>>()?,
+    ];
+    let rows = if key_columns.is_empty() {
+        rows_by_position(&files)
+    } else {
+        rows_by_key(&files, columns, key_columns, nulls)
+    };
+
+    let same = |column: usize, left: Option<&str>, right: Option<&str>| match (left, right) {
+        (Some(left), Some(right)) => match columns.get(column) {
+            Some(comparison) => comparison.compare(left, right, nulls) == CellComparison::Same,
+            None => left == right,
+        },
+        (left, right) => left == right,
+    };
+    let mut comparison = ThreeWayComparison {
+        base_filename: String::new(),
+        ours_filename: String::new(),
+        theirs_filename: String::new(),
+        counts: ChangeCounts::default(),
+        conflicts: vec![],
+        changes: vec![],
+        found_max_cells: false,
+    };
+    for (row, [base, ours, theirs]) in rows {
+        let num_columns = [base, ours, theirs]
+            .iter()
+            .map(|record| record.map_or(0, |record| record.len()))
+            .max()
+            .unwrap_or(0);
+        for column in 0..num_columns {
+            if columns
+                .get(column)
+                .is_some_and(|comparison| !comparison.compared)
+            {
+                continue;
+            }
+            let [base_cell, ours_cell, theirs_cell] =
+                [base, ours, theirs].map(|record| record.and_then(|record| record.get(column)));
+            let change = CellChange::classify(
+                same(column, base_cell, ours_cell),
+                same(column, base_cell, theirs_cell),
+                same(column, ours_cell, theirs_cell),
+            );
+            let counts = &mut comparison.counts;
+            match change {
+                CellChange::Unchanged => counts.unchanged += 1,
+                CellChange::ChangedInOurs => counts.changed_in_ours += 1,
+                CellChange::ChangedInTheirs => counts.changed_in_theirs += 1,
+                CellChange::ChangedIdentically => counts.changed_identically += 1,
+                CellChange::Conflicting => counts.conflicting += 1,
+            }
+            if change == CellChange::Unchanged {
+                continue;
+            }
+            if change != CellChange::Conflicting && comparison.changes.len() >= max_cells {
+                comparison.found_max_cells = true;
+                continue;
+            }
+            let changed_cell = ChangedCell {
+                row: row.clone(),
+                column: match column_names.get(column) {
+                    Some(name) if !name.is_empty() => format!("{} ({})", column + 1, name),
+                    _ => (column + 1).to_string(),
+                },
+                change: change.label(),
+                base: display_cell(base_cell),
+                ours: display_cell(ours_cell),
+                theirs: display_cell(theirs_cell),
+            };
+            match change {
+                CellChange::Conflicting => comparison.conflicts.push(changed_cell),
+                _ => comparison.changes.push(changed_cell),
+            }
+        }
+    }
+    Ok(comparison)
+}
+
+fn display_cell(cell: Option<&str>) -> String {
+    match cell {
+        None => "missing".to_string(),
+        Some("") => "empty".to_string(),
+        Some(cell) => cell.to_string(),
+    }
+}
+
+fn rows_by_position(files: &[Vec<csv::StringRecord>; 3]) -> Vec<RowSet<'_>> {
+    let num_rows = files.iter().map(Vec::len).max().unwrap_or(0);
+    (0..num_rows)
+        .map(|row| {
+            (
+                format!("record {}", row + 1),
+                [files[0].get(row), files[1].get(row), files[2].get(row)],
+            )
+        })
+        .collect()
+}
+
+/// Lines up rows by key, in the order the keys first appear in the base file, then in ours, then in theirs.  Keys are
+/// matched with nulls and values of each column's type written the same way, and shown as the first row with them
+/// writes them.
+fn rows_by_key<'a>(
+    files: &'a [Vec<csv::StringRecord>; 3],
+    columns: &[ColumnComparison],
+    key_columns: &[usize],
+    nulls: &Nulls,
+) -> Vec<RowSet<'a>> {
+    let key = |record: &csv::StringRecord| -> Vec<String> {
+        key_columns
+            .iter()
+            .map(|column| {
+                let cell = record.get(*column).unwrap_or_default();
+                cells::cell_key(columns, *column, cell, nulls).into_owned()
+            })
+            .collect()
+    };
+    let shown_key = |record: &csv::StringRecord| {
+        key_columns
+            .iter()
+            .map(|column| record.get(*column).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let mut rows: Vec<RowSet> = vec![];
+    let mut row_indexes: HashMap<(Vec<String>, usize), usize> = HashMap::new();
+    for (file, records) in files.iter().enumerate() {
+        let mut occurrences: HashMap<Vec<String>, usize> = HashMap::new();
+        for record in records {
+            let key = key(record);
+            let occurrence = occurrences.entry(key.clone()).or_default();
+            let label = match occurrence {
+                0 => format!("key {}", shown_key(record)),
+                _ => format!(
+                    "key {} (row {} with it)",
+                    shown_key(record),
+                    *occurrence + 1
+                ),
+            };
+            let index = *row_indexes.entry((key, *occurrence)).or_insert_with(|| {
+                rows.push((label, [None, None, None]));
+                rows.len() - 1
+            });
+            rows[index].1[file] = Some(record);
+            *occurrence += 1;
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file already read into memory.
+    struct Rows {
+        headers: csv::StringRecord,
+        records: std::vec::IntoIter<csv::StringRecord>,
+    }
+
+    impl Iterator for Rows {
+        type Item = Result<csv::StringRecord, SourceError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.records.next().map(Ok)
+        }
+    }
+
+    impl Source for Rows {
+        fn headers(&self) -> &csv::StringRecord {
+            &self.headers
+        }
+    }
+
+    fn record(cells: &str) -> csv::StringRecord {
+        cells.split(',').collect()
+    }
+
+    fn rows(records: &[&str]) -> Rows {
+        Rows {
+            headers: record("id,v,w"),
+            records: records
+                .iter()
+                .map(|cells| record(cells))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        }
+    }
+
+    #[test]
+    fn classifies_each_kind_of_change() {
+        assert_eq!(
+            CellChange::classify(true, true, true),
+            CellChange::Unchanged
+        );
+        assert_eq!(
+            CellChange::classify(false, true, false),
+            CellChange::ChangedInOurs
+        );
+        assert_eq!(
+            CellChange::classify(true, false, false),
+            CellChange::ChangedInTheirs
+        );
+        assert_eq!(
+            CellChange::classify(false, false, true),
+            CellChange::ChangedIdentically
+        );
+        assert_eq!(
+            CellChange::classify(false, false, false),
+            CellChange::Conflicting
+        );
+    }
+
+    #[test]
+    fn lines_up_rows_by_key_across_all_three_files() {
+        let files = [
+            vec![record("1,a,x"), record("2,b,y"), record("2,c,y")],
+            vec![record("2,b,y"), record("3,d,z")],
+            vec![record("1,a,x"), record("2,c,y"), record("2,b,y")],
+        ];
+        let rows: Vec<(String, [Option<String>; 3])> =
+            rows_by_key(&files, &[], &[0], &Nulls::default())
+                .into_iter()
+                .map(|(label, records)| {
+                    let cells = records.map(|record| {
+                        record.map(|record| record.iter().collect::<Vec<_>>().join(","))
+                    });
+                    (label, cells)
+                })
+                .collect();
+        let cells = |base: Option<&str>, ours: Option<&str>, theirs: Option<&str>| {
+            [base, ours, theirs].map(|cells| cells.map(String::from))
+        };
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "key 1".to_string(),
+                    cells(Some("1,a,x"), None, Some("1,a,x"))
+                ),
+                (
+                    "key 2".to_string(),
+                    cells(Some("2,b,y"), Some("2,b,y"), Some("2,c,y"))
+                ),
+                (
+                    "key 2 (row 2 with it)".to_string(),
+                    cells(Some("2,c,y"), None, Some("2,b,y"))
+                ),
+                ("key 3".to_string(), cells(None, Some("3,d,z"), None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_up_null_keys_however_they_are_written() {
+        let files = [
+            vec![record("NULL,a")],
+            vec![record(",a")],
+            vec![record("\\N,b")],
+        ];
+        let nulls = Nulls {
+            tokens: Nulls::DEFAULT_TOKENS
+                .iter()
+                .map(|token| token.to_string())
+                .collect(),
+            report_separately: false,
+        };
+        let rows = rows_by_key(&files, &[], &[0], &nulls);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, "key NULL");
+        assert!(rows[0].1.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn counts_changes_including_rows_missing_from_one_file() {
+        let mut base = rows(&["1,a,x", "2,b,y", "3,c,z", "5,e,v"]);
+        let mut ours = rows(&["1,A,x", "2,b,y", "3,C,z", "5,E,v", "4,d,w"]);
+        let mut theirs = rows(&["1,a,X", "3,D,z", "5,E,v"]);
+        let column_names: Vec<String> = vec!["id".into(), "v".into(), "w".into()];
+        let comparison = compare(
+            [&mut base, &mut ours, &mut theirs],
+            &[],
+            &column_names,
+            &[0],
+            &Nulls::default(),
+            usize::MAX,
+        )
+        .unwrap();
+
+        let counts = &comparison.counts;
+        assert_eq!(
+            (
+                counts.unchanged,
+                counts.changed_in_ours,
+                counts.changed_in_theirs,
+                counts.changed_identically,
+                counts.conflicting
+            ),
+            (5, 4, 4, 1, 1)
+        );
+        assert_eq!(
+            comparison
+                .conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["Conflicting at key 3, column 2 (v) (base c, ours C, theirs D)"]
+        );
+        assert!(comparison.changes.iter().any(|cell| cell.to_string()
+            == "Changed in theirs at key 2, column 3 (w) (base y, ours y, theirs missing)"));
+        assert!(comparison.changes.iter().any(|cell| cell.to_string()
+            == "Changed in ours at key 4, column 2 (v) (base missing, ours d, theirs missing)"));
+    }
+
+    #[test]
+    fn lists_conflicts_past_the_most_cells() {
+        let mut base = rows(&["1,a,x", "2,b,y"]);
+        let mut ours = rows(&["1,A,x", "2,B,y"]);
+        let mut theirs = rows(&["1,a,X", "2,C,y"]);
+        let comparison = compare(
+            [&mut base, &mut ours, &mut theirs],
+            &[],
+            &[],
+            &[],
+            &Nulls::default(),
+            1,
+        )
+        .unwrap();
+        assert_eq!(comparison.changes.len(), 1);
+        assert!(comparison.found_max_cells);
+        assert_eq!(
+            comparison
+                .conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["Conflicting at record 2, column 2 (base b, ours B, theirs C)"]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::cells::{self, ColumnComparison, Nulls};
+use crate::problems::RowProblem;
+use crate::sources::SourceError;
+
+/// How many different rows are counted in memory before the rest are spread across partition files on disk.
+const MAX_ROWS_IN_MEMORY: usize = 1_000_000;
+/// How many partition files rows are spread across, so that each one can be counted in memory on its own.
+const NUM_PARTITIONS: u64 = 64;
+
+/// How often a row appears in each file, and the record it first appears at in each.
+#[derive(Debug, Clone, Default)]
+struct RowCount {
+    row: String,
+    expected_count: usize,
+    actual_count: usize,
+    expected_line: Option<usize>,
+    actual_line: Option<usize>,
+}
+
+impl RowCount {
+    fn add(&mut self, other: RowCount) {
+        if self.row.is_empty() {
+            self.row = other.row;
+        }
+        self.expected_count += other.expected_count;
+        self.actual_count += other.actual_count;
+        self.expected_line = self.expected_line.or(other.expected_line);
+        self.actual_line = self.actual_line.or(other.actual_line);
+    }
+
+    fn problem(self) -> Option<RowProblem> {
+        match (self.expected_count, self.actual_count) {
+            (expected_count, actual_count) if expected_count == actual_count => None,
+            (0, actual_count) => Some(RowProblem::ExtraRow {
+                line: self.actual_line.unwrap_or_default(),
+                row: self.row,
+                count: actual_count,
+            }),
+            (expected_count, 0) => Some(RowProblem::MissingRow {
+                line: self.expected_line.unwrap_or_default(),
+                row: self.row,
+                count: expected_count,
+            }),
+            (expected_count, actual_count) => Some(RowProblem::DuplicateRow {
+                expected_line: self.expected_line.unwrap_or_default(),
+                actual_line: self.actual_line.unwrap_or_default(),
+                row: self.row,
+                expected_count,
+                actual_count,
+            }),
+        }
+    }
+}
+
+/// Counts rows, moving the counts out to partition files once there are too many different rows to keep in memory.
+struct RowCounter<'a> {
+    temp_dir: &'a Path,
+    max_rows_in_memory: usize,
+    counts: HashMap<String, RowCount>,
+    partitions: Vec<csv::Writer<File>>,
+}
+
+impl<'a> RowCounter<'a> {
+    fn add(&mut self, key: String, count: RowCount) -> Result<(), SourceError> {
+        if self.partitions.is_empty() {
+            self.counts.entry(key).or_default().add(count);
+            if self.counts.len() > self.max_rows_in_memory {
+                self.spill()?;
+            }
+            Ok(())
+        } else {
+            self.write(&key, &count)
+        }
+    }
+
+    fn spill(&mut self) -> Result<(), SourceError> {
+        for _ in 0..NUM_PARTITIONS {
+            self.partitions
+                .push(csv::Writer::from_writer(tempfile::tempfile_in(
+                    self.temp_dir,
+                )?));
+        }
+        for (key, count) in std::mem::take(&mut self.counts) {
+            self.write(&key, &count)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, key: &str, count: &RowCount) -> Result<(), SourceError> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let partition = &mut self.partitions[(hasher.finish() % NUM_PARTITIONS) as usize];
+        let line_text = |line: Option<usize>| line.map(|line| line.to_string()).unwrap_or_default();
+        partition.write_record([
+            key,
+            &count.row,
+            &count.expected_count.to_string(),
+            &count.actual_count.to_string(),
+            &line_text(count.expected_line),
+            &line_text(count.actual_line),
+        ])?;
+        Ok(())
+    }
+
+    /// Finds the rows whose counts differ, reading each partition back in turn if any were written.
+    fn problems(self) -> Result<Vec<RowProblem>, SourceError> {
+        if self.partitions.is_empty() {
+            return Ok(self
+                .counts
+                .into_values()
+                .filter_map(RowCount::problem)
+                .collect());
+        }
+        let mut problems = vec![];
+        for partition in self.partitions {
+            let mut file = partition
+                .into_inner()
+                .map_err(|error| SourceError::IO(error.into_error()))?;
+            file.seek(SeekFrom::Start(0))?;
+            let mut counts: HashMap<String, RowCount> = HashMap::new();
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(file);
+            for record in reader.records() {
+                let record = record?;
+                let number = |field: usize| record.get(field).and_then(|value| value.parse().ok());
+                counts
+                    .entry(record.get(0).unwrap_or_default().to_string())
+                    .or_default()
+                    .add(RowCount {
+                        row: record.get(1).unwrap_or_default().to_string(),
+                        expected_count: number(2).unwrap_or_default(),
+                        actual_count: number(3).unwrap_or_default(),
+                        expected_line: number(4),
+                        actual_line: number(5),
+                    });
+            }
+            problems.extend(counts.into_values().filter_map(RowCount::problem));
+        }
+        Ok(problems)
+    }
+}
+
+/// Compares the files as collections of rows, ignoring their order.  Rows are matched after their cells are rewritten
+/// and normalized, with nulls and values of each column's type written the same way, so rows that only differ in ways
+/// that are being ignored still count as the same.
+pub fn compare(
+    expected: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+    actual: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+    columns: &[ColumnComparison],
+    nulls: &Nulls,
+    temp_dir: &Path,
+) -> Result<Vec<RowProblem>, SourceError> {
+    compare_with_limit(
+        expected,
+        actual,
+        columns,
+        nulls,
+        temp_dir,
+        MAX_ROWS_IN_MEMORY,
+    )
+}
+
+/// Compares the files as collections of rows, counting at most `max_rows_in_memory` different rows in memory.
+fn compare_with_limit(
+    expected: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+    actual: impl Iterator<Item = Result<csv::StringRecord, SourceError>>,
+    columns: &[ColumnComparison],
+    nulls: &Nulls,
+    temp_dir: &Path,
+    max_rows_in_memory: usize,
+) -> Result<Vec<RowProblem>, SourceError> {
+    let mut counter = RowCounter {
+        temp_dir,
+        max_rows_in_memory,
+        counts: HashMap::new(),
+        partitions: vec![],
+    };
+    let key = |record: &csv::StringRecord| {
+        (0..)
+            .zip(record.iter())
+            .map(|(column, cell)| match columns.get(column) {
+                Some(comparison) if !comparison.compared => String::new(),
+                _ => cells::cell_key(columns, column, cell, nulls).into_owned(),
+            })
+            .join("\u{1f}")
+    };
+    for (line, record) in (1..).zip(expected) {
+        let record = record?;
+        counter.add(
+            key(&record),
+            RowCount {
+                row: record.iter().join(","),
+                expected_count: 1,
+                expected_line: Some(line),
+                ..RowCount::default()
+            },
+        )?;
+    }
+    for (line, record) in (1..).zip(actual) {
+        let record = record?;
+        counter.add(
+            key(&record),
+            RowCount {
+                row: record.iter().join(","),
+                actual_count: 1,
+                actual_line: Some(line),
+                ..RowCount::default()
+            },
+        )?;
+    }
+    counter.problems()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize::Normalization;
+    use crate::sources::ColumnType;
+
+    fn records(rows: &[&str]) -> impl Iterator<Item = Result<csv::StringRecord, SourceError>> {
+        rows.iter()
+            .map(|row| Ok(row.split(',').collect::<csv::StringRecord>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn problems(expected: &[&str], actual: &[&str], max_rows_in_memory: usize) -> Vec<String> {
+        problems_with(expected, actual, &[], &Nulls::default(), max_rows_in_memory)
+    }
+
+    fn problems_with(
+        expected: &[&str],
+        actual: &[&str],
+        columns: &[ColumnComparison],
+        nulls: &Nulls,
+        max_rows_in_memory: usize,
+    ) -> Vec<String> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut problems: Vec<String> = compare_with_limit(
+            records(expected),
+            records(actual),
+            columns,
+            nulls,
+            temp_dir.path(),
+            max_rows_in_memory,
+        )
+        .unwrap()
+        .iter()
+        .map(|problem| format!("{:?}", problem))
+        .collect();
+        problems.sort();
+        problems
+    }
+
+    const EXPECTED: [&str; 6] = ["1,a", "2,b", "3,c", "3,c", "4,d", "5,e"];
+    const ACTUAL: [&str; 6] = ["5,e", "3,c", "6,f", "2,b", "2,b", "1,a"];
+
+    #[test]
+    fn counts_rows_in_memory() {
+        assert_eq!(
+            problems(&EXPECTED, &ACTUAL, usize::MAX),
+            vec![
+                "DuplicateRow { expected_line: 2, actual_line: 4, row: \"2,b\", expected_count: 1, actual_count: 2 }",
+                "DuplicateRow { expected_line: 3, actual_line: 2, row: \"3,c\", expected_count: 2, actual_count: 1 }",
+                "ExtraRow { line: 3, row: \"6,f\", count: 1 }",
+                "MissingRow { line: 5, row: \"4,d\", count: 1 }",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_rows_in_partitions() {
+        for max_rows_in_memory in 0..4 {
+            assert_eq!(
+                problems(&EXPECTED, &ACTUAL, max_rows_in_memory),
+                problems(&EXPECTED, &ACTUAL, usize::MAX)
+            );
+        }
+    }
+
+    #[test]
+    fn same_rows_in_any_order() {
+        assert!(problems(&EXPECTED, &["5,e", "4,d", "3,c", "2,b", "1,a", "3,c"], 1).is_empty());
+    }
+
+    #[test]
+    fn same_values_written_differently() {
+        let column = |column_type| ColumnComparison {
+            column_type,
+            checks_type: false,
+            rewrites: vec![],
+            normalization: Normalization::default(),
+            compared: true,
+        };
+        let columns = [
+            column(ColumnType::Integer),
+            column(ColumnType::Decimal),
+            column(ColumnType::Boolean),
+            column(ColumnType::Text),
+        ];
+        let nulls = Nulls {
+            tokens: Nulls::DEFAULT_TOKENS
+                .iter()
+                .map(|token| token.to_string())
+                .collect(),
+            report_separately: false,
+        };
+        let expected = ["1,1.0,TRUE,NULL", "2,2.50,false,x"];
+        let actual = ["2,2.5,False,x", "+1,1,true,"];
+        for max_rows_in_memory in [0, usize::MAX] {
+            assert!(
+                problems_with(&expected, &actual, &columns, &nulls, max_rows_in_memory).is_empty()
+            );
+        }
+        assert_eq!(
+            problems_with(
+                &["1,1.0,true,x"],
+                &["1,1.0,true,"],
+                &columns,
+                &nulls,
+                usize::MAX
+            ),
+            vec![
+                "ExtraRow { line: 1, row: \"1,1.0,true,\", count: 1 }",
+                "MissingRow { line: 1, row: \"1,1.0,true,x\", count: 1 }",
+            ]
+        );
+    }
+}